@@ -0,0 +1,35 @@
+use disasm;
+use runner::{TestCase, TestError};
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Everything needed to look at (and reproduce) a failed case, in <dir>/<test name>-<seed>/:
+//  rom.bin           the test rom, as loaded at the rom address
+//  rom_addr.txt      where the rom was loaded
+//  initial_regs.txt  one reg per line
+//  listing.txt       disassembly of the rom, one instruction per line with its offset
+//  error.txt         what went wrong
+// Returns the case's directory, so more (eg. traces) can be written next to these.
+pub fn write_failure<P: AsRef<Path>>(dir: P, test_name: &str, seed: usize, case: &TestCase, error: &TestError) -> io::Result<PathBuf> {
+    let case_dir = dir.as_ref().join(format!("{}-{}", test_name, seed));
+    fs::create_dir_all(&case_dir)?;
+
+    File::create(case_dir.join("rom.bin"))?.write_all(&case.rom)?;
+    File::create(case_dir.join("rom_addr.txt"))?.write_all(format!("0x{:08x}\n", case.rom_addr).as_bytes())?;
+
+    let initial_regs = case.initial_regs.iter().enumerate().map(|(reg, value)| format!("r{}: 0x{:08x}\n", reg, value)).collect::<String>();
+    File::create(case_dir.join("initial_regs.txt"))?.write_all(initial_regs.as_bytes())?;
+
+    File::create(case_dir.join("listing.txt"))?.write_all(disasm::listing(&case.rom).as_bytes())?;
+
+    File::create(case_dir.join("error.txt"))?.write_all(format!("{}\n", error).as_bytes())?;
+
+    Ok(case_dir)
+}
+
+// Traces use the same offsets as listing.txt, so the two line up
+pub fn write_trace<P: AsRef<Path>>(case_dir: P, target_name: &str, trace: &str) -> io::Result<()> {
+    File::create(case_dir.as_ref().join(format!("trace-{}.txt", target_name)))?.write_all(trace.as_bytes())
+}
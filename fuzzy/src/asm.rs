@@ -0,0 +1,161 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+
+// Encoders for directed generators, which lay out whole programs by hand rather than one random instruction at a
+//  time. Operands are in assembly order.
+
+// Bcond conditions
+pub const COND_Z: u16 = 2;
+pub const COND_ALWAYS: u16 = 5;
+pub const COND_NZ: u16 = 10;
+
+// System reg IDs for ldsr/stsr
+pub const PSW: u16 = 5;
+pub const CHCW: u16 = 24;
+
+fn write_format_i(buf: &mut Vec<u8>, op: u16, reg1: u16, reg2: u16) {
+    buf.write_u16::<LittleEndian>((op << 10) | (reg2 << 5) | reg1).unwrap();
+}
+
+fn write_format_iv(buf: &mut Vec<u8>, op: u16, disp26: i32) {
+    let disp26 = (disp26 as u32) & 0x03ff_ffff;
+    buf.write_u16::<LittleEndian>((op << 10) | ((disp26 >> 16) as u16)).unwrap();
+    buf.write_u16::<LittleEndian>(disp26 as u16).unwrap();
+}
+
+fn write_format_v(buf: &mut Vec<u8>, op: u16, imm16: u16, reg1: u16, reg2: u16) {
+    buf.write_u16::<LittleEndian>((op << 10) | (reg2 << 5) | reg1).unwrap();
+    buf.write_u16::<LittleEndian>(imm16).unwrap();
+}
+
+pub fn mov(buf: &mut Vec<u8>, reg1: u16, reg2: u16) {
+    write_format_i(buf, 0b000000, reg1, reg2);
+}
+
+pub fn add(buf: &mut Vec<u8>, reg1: u16, reg2: u16) {
+    write_format_i(buf, 0b000001, reg1, reg2);
+}
+
+pub fn sub(buf: &mut Vec<u8>, reg1: u16, reg2: u16) {
+    write_format_i(buf, 0b000010, reg1, reg2);
+}
+
+pub fn cmp(buf: &mut Vec<u8>, reg1: u16, reg2: u16) {
+    write_format_i(buf, 0b000011, reg1, reg2);
+}
+
+pub fn shl(buf: &mut Vec<u8>, reg1: u16, reg2: u16) {
+    write_format_i(buf, 0b000100, reg1, reg2);
+}
+
+pub fn jmp(buf: &mut Vec<u8>, reg1: u16) {
+    write_format_i(buf, 0b000110, reg1, 0);
+}
+
+pub fn mul(buf: &mut Vec<u8>, reg1: u16, reg2: u16) {
+    write_format_i(buf, 0b001000, reg1, reg2);
+}
+
+pub fn div(buf: &mut Vec<u8>, reg1: u16, reg2: u16) {
+    write_format_i(buf, 0b001001, reg1, reg2);
+}
+
+pub fn mulu(buf: &mut Vec<u8>, reg1: u16, reg2: u16) {
+    write_format_i(buf, 0b001010, reg1, reg2);
+}
+
+pub fn divu(buf: &mut Vec<u8>, reg1: u16, reg2: u16) {
+    write_format_i(buf, 0b001011, reg1, reg2);
+}
+
+pub fn or(buf: &mut Vec<u8>, reg1: u16, reg2: u16) {
+    write_format_i(buf, 0b001100, reg1, reg2);
+}
+
+pub fn and(buf: &mut Vec<u8>, reg1: u16, reg2: u16) {
+    write_format_i(buf, 0b001101, reg1, reg2);
+}
+
+pub fn xor(buf: &mut Vec<u8>, reg1: u16, reg2: u16) {
+    write_format_i(buf, 0b001110, reg1, reg2);
+}
+
+pub fn mov_imm(buf: &mut Vec<u8>, imm5: i16, reg2: u16) {
+    write_format_i(buf, 0b010000, (imm5 as u16) & 0x1f, reg2);
+}
+
+pub fn setf(buf: &mut Vec<u8>, cond: u16, reg2: u16) {
+    write_format_i(buf, 0b010010, cond & 0x0f, reg2);
+}
+
+pub fn shr_imm(buf: &mut Vec<u8>, imm5: u16, reg2: u16) {
+    write_format_i(buf, 0b010101, imm5 & 0x1f, reg2);
+}
+
+pub fn add_imm(buf: &mut Vec<u8>, imm5: i16, reg2: u16) {
+    write_format_i(buf, 0b010001, (imm5 as u16) & 0x1f, reg2);
+}
+
+pub fn ldsr(buf: &mut Vec<u8>, reg2: u16, reg_id: u16) {
+    write_format_i(buf, 0b011100, reg_id, reg2);
+}
+
+pub fn stsr(buf: &mut Vec<u8>, reg_id: u16, reg2: u16) {
+    write_format_i(buf, 0b011101, reg_id, reg2);
+}
+
+pub fn bcond(buf: &mut Vec<u8>, cond: u16, disp9: i16) {
+    let op = (0b100 << 4) | cond;
+    buf.write_u16::<LittleEndian>((op << 9) | ((disp9 as u16) & 0x01ff)).unwrap();
+}
+
+pub fn jal(buf: &mut Vec<u8>, disp26: i32) {
+    write_format_iv(buf, 0b101011, disp26);
+}
+
+pub fn movea(buf: &mut Vec<u8>, imm16: i16, reg1: u16, reg2: u16) {
+    write_format_v(buf, 0b101000, imm16 as u16, reg1, reg2);
+}
+
+pub fn addi(buf: &mut Vec<u8>, imm16: i16, reg1: u16, reg2: u16) {
+    write_format_v(buf, 0b101001, imm16 as u16, reg1, reg2);
+}
+
+pub fn ori(buf: &mut Vec<u8>, imm16: u16, reg1: u16, reg2: u16) {
+    write_format_v(buf, 0b101100, imm16, reg1, reg2);
+}
+
+pub fn xori(buf: &mut Vec<u8>, imm16: u16, reg1: u16, reg2: u16) {
+    write_format_v(buf, 0b101110, imm16, reg1, reg2);
+}
+
+pub fn movhi(buf: &mut Vec<u8>, imm16: u16, reg1: u16, reg2: u16) {
+    write_format_v(buf, 0b101111, imm16, reg1, reg2);
+}
+
+pub fn andi(buf: &mut Vec<u8>, imm16: u16, reg1: u16, reg2: u16) {
+    write_format_v(buf, 0b101101, imm16, reg1, reg2);
+}
+
+pub fn ld_b(buf: &mut Vec<u8>, disp16: i16, reg1: u16, reg2: u16) {
+    write_format_v(buf, 0b110000, disp16 as u16, reg1, reg2);
+}
+
+pub fn ld_h(buf: &mut Vec<u8>, disp16: i16, reg1: u16, reg2: u16) {
+    write_format_v(buf, 0b110001, disp16 as u16, reg1, reg2);
+}
+
+pub fn ld_w(buf: &mut Vec<u8>, disp16: i16, reg1: u16, reg2: u16) {
+    write_format_v(buf, 0b110011, disp16 as u16, reg1, reg2);
+}
+
+pub fn st_b(buf: &mut Vec<u8>, reg2: u16, disp16: i16, reg1: u16) {
+    write_format_v(buf, 0b110100, disp16 as u16, reg1, reg2);
+}
+
+pub fn st_h(buf: &mut Vec<u8>, reg2: u16, disp16: i16, reg1: u16) {
+    write_format_v(buf, 0b110101, disp16 as u16, reg1, reg2);
+}
+
+pub fn st_w(buf: &mut Vec<u8>, reg2: u16, disp16: i16, reg1: u16) {
+    write_format_v(buf, 0b110111, disp16 as u16, reg1, reg2);
+}
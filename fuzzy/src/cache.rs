@@ -0,0 +1,228 @@
+use rand::{Rng, StdRng};
+
+use asm::*;
+use generators::Generator;
+
+// CHCW bits. CEC/CEN (which entries a clear covers) and SA (where dumps and restores go) share the upper bits.
+const CHCW_ICC: u16 = 0x0001;
+const CHCW_ICE: u16 = 0x0002;
+const CHCW_ICD: u16 = 0x0010;
+const CHCW_ICR: u16 = 0x0020;
+const NUM_CACHE_ENTRIES: u16 = 128;
+const CACHE_ENTRY_LEN: u16 = 8;
+// Every entry, starting from the first
+const CHCW_CLEAR_ALL: u16 = (NUM_CACHE_ENTRIES << 8) | CHCW_ICC;
+
+// Dumps go to the start of the scratch region (see memory.rs), which is 256-byte aligned like SA needs. This is
+//  comfortably more than a dump of the whole cache, and gets zeroed before the final dump so whatever the dump
+//  doesn't cover compares equal.
+const DUMP_ADDR_HI: u16 = 0x0501;
+// 0x05010000 - 0x8000 = 0x05008000
+const DUMP_ADDR_LO: u16 = 0x8000;
+const DUMP_LEN: u16 = 0x0800;
+
+// Regs the program keeps for itself
+const SLOT_REG: u16 = 10;
+const VALUE_REG: u16 = 11;
+const COUNTER_REG: u16 = 12;
+const CHCW_REG: u16 = 13;
+// Results
+const ACCUMULATOR_REG: u16 = 14;
+const CHCW_READBACK_REG: u16 = 15;
+const DUMP_SUM_REG: u16 = 16;
+// Holds the return addr of the jal that finds the slot
+const LINK_REG: u16 = 31;
+
+const MAX_PASSES: u16 = 512;
+
+// Format V ops the slot can hold. They all use the imm16 and take r14 to r14, so patching one for another (or
+//  changing the imm) changes what gets accumulated.
+const SLOT_OPS: [u16; 4] = [
+    0b101000, // movea
+    0b101001, // addi
+    0b101100, // ori
+    0b101110, // xori
+];
+
+#[derive(Clone, Copy)]
+enum Patch {
+    // The pass counter goes in as the new imm16
+    Imm,
+    // Flips the op between two of SLOT_OPS
+    Op,
+    Both,
+}
+
+// What happens to the cache after each patch
+#[derive(Clone, Copy)]
+enum Maintenance {
+    Nothing,
+    ClearAll,
+    // CEN, CEC
+    ClearRange(u16, u16),
+    // Disabling doesn't clear the cache, so whatever it held comes back when it's enabled again
+    DisableEnable,
+    // Dumps before the patch and restores afterwards, putting the old code back in the cache
+    DumpRestore,
+}
+
+// Self-modifying code under different cache settings. A 4-byte slot gets run, patched with a store, and run again,
+//  over and over, with the slot accumulating into r14. The cache is enabled or not, and each patch is followed by
+//  some (or no) cache maintenance through CHCW. A cache holding a stale copy of the slot changes what gets
+//  accumulated, and how often the slot is fetched from memory changes how long it all takes, so these are timed.
+//
+// Afterwards CHCW is read back into r15, and the whole cache is dumped and summed into r16. Every entry is filled
+//  with known code first, so the dump doesn't depend on earlier tests. The cache is cleared and disabled before
+//  returning, as the loader expects.
+pub struct CacheSelfModify {
+    rng: StdRng,
+}
+
+impl CacheSelfModify {
+    pub fn new(rng: StdRng) -> CacheSelfModify {
+        CacheSelfModify {
+            rng: rng,
+        }
+    }
+
+    fn random_maintenance(&mut self) -> Maintenance {
+        match self.rng.gen::<u32>() % 5 {
+            0 => Maintenance::Nothing,
+            1 => Maintenance::ClearAll,
+            2 => {
+                let start = self.rng.gen_range(0, NUM_CACHE_ENTRIES);
+                let count = self.rng.gen_range(1, NUM_CACHE_ENTRIES - start + 1);
+                Maintenance::ClearRange(start, count)
+            }
+            3 => Maintenance::DisableEnable,
+            _ => Maintenance::DumpRestore,
+        }
+    }
+}
+
+impl Generator for CacheSelfModify {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let enable = if self.rng.gen() { CHCW_ICE } else { 0 };
+        let patch = *self.rng.choose(&[Patch::Imm, Patch::Op, Patch::Both]).unwrap();
+        let maintenance = self.random_maintenance();
+        let passes = self.rng.gen_range(2, MAX_PASSES + 1);
+
+        let mut ops = SLOT_OPS;
+        self.rng.shuffle(&mut ops);
+        let first_halfwords = [
+            (ops[0] << 10) | (ACCUMULATOR_REG << 5) | ACCUMULATOR_REG,
+            (ops[1] << 10) | (ACCUMULATOR_REG << 5) | ACCUMULATOR_REG,
+        ];
+        let initial_imm = self.rng.gen::<u16>();
+
+        // Clearing only marks entries invalid, and dumps include invalid entries as they are, so whatever earlier tests
+        //  left in the cache would end up in the dump sum. Running straight through more code than the cache holds
+        //  with it enabled overwrites every entry the same way on every target.
+        ori(buf, CHCW_ICE, 0, CHCW_REG);
+        ldsr(buf, CHCW_REG, CHCW);
+        for _ in 0..(NUM_CACHE_ENTRIES + 1) * CACHE_ENTRY_LEN / 2 {
+            mov(buf, 0, 0);
+        }
+
+        // Start from an empty cache either way
+        ori(buf, CHCW_CLEAR_ALL | enable, 0, CHCW_REG);
+        ldsr(buf, CHCW_REG, CHCW);
+
+        mov(buf, 0, ACCUMULATOR_REG);
+        movea(buf, passes as i16, 0, COUNTER_REG);
+        ori(buf, first_halfwords[0], 0, VALUE_REG);
+
+        // The slot comes right after the movea, which is 4 bytes past the jal's return addr
+        jal(buf, 4);
+        movea(buf, 4, LINK_REG, SLOT_REG);
+
+        // Everything after the slot is laid out first, so the branches around it know how far to go
+        let mut patch_block = Vec::new();
+        if let Maintenance::DumpRestore = maintenance {
+            write_dump_addr(&mut patch_block, CHCW_ICD | enable);
+            ldsr(&mut patch_block, CHCW_REG, CHCW);
+        }
+        match patch {
+            Patch::Imm | Patch::Both => st_h(&mut patch_block, COUNTER_REG, 2, SLOT_REG),
+            _ => (),
+        }
+        match patch {
+            Patch::Op | Patch::Both => {
+                xori(&mut patch_block, first_halfwords[0] ^ first_halfwords[1], VALUE_REG, VALUE_REG);
+                st_h(&mut patch_block, VALUE_REG, 0, SLOT_REG);
+            }
+            _ => (),
+        }
+        match maintenance {
+            Maintenance::Nothing => (),
+            Maintenance::ClearAll => {
+                ori(&mut patch_block, CHCW_CLEAR_ALL | enable, 0, CHCW_REG);
+                ldsr(&mut patch_block, CHCW_REG, CHCW);
+            }
+            Maintenance::ClearRange(start, count) => {
+                movhi(&mut patch_block, start << 4, 0, CHCW_REG);
+                ori(&mut patch_block, (count << 8) | CHCW_ICC | enable, CHCW_REG, CHCW_REG);
+                ldsr(&mut patch_block, CHCW_REG, CHCW);
+            }
+            Maintenance::DisableEnable => {
+                ldsr(&mut patch_block, 0, CHCW);
+                ori(&mut patch_block, enable, 0, CHCW_REG);
+                ldsr(&mut patch_block, CHCW_REG, CHCW);
+            }
+            Maintenance::DumpRestore => {
+                write_dump_addr(&mut patch_block, CHCW_ICR | enable);
+                ldsr(&mut patch_block, CHCW_REG, CHCW);
+            }
+        }
+
+        // slot: op imm, r14, r14 (4)
+        //       add -1, r12      (2)
+        //       bz done          (2)
+        //       patch block
+        //       br slot          (2)
+        // done:
+        buf.extend(&[first_halfwords[0] as u8, (first_halfwords[0] >> 8) as u8, initial_imm as u8, (initial_imm >> 8) as u8]);
+        add_imm(buf, -1, COUNTER_REG);
+        bcond(buf, COND_Z, (2 + patch_block.len() + 2) as i16);
+        buf.extend(&patch_block);
+        bcond(buf, COND_ALWAYS, -((4 + 2 + 2 + patch_block.len()) as i16));
+
+        stsr(buf, CHCW, CHCW_READBACK_REG);
+
+        // Zero the dump area, then dump the whole cache there and sum it up
+        movhi(buf, DUMP_ADDR_HI, 0, SLOT_REG);
+        movea(buf, DUMP_ADDR_LO as i16, SLOT_REG, SLOT_REG);
+        movea(buf, (DUMP_LEN / 4) as i16, 0, COUNTER_REG);
+        st_w(buf, 0, 0, SLOT_REG);
+        add_imm(buf, 4, SLOT_REG);
+        add_imm(buf, -1, COUNTER_REG);
+        bcond(buf, COND_NZ, -8);
+
+        write_dump_addr(buf, CHCW_ICD | enable);
+        ldsr(buf, CHCW_REG, CHCW);
+
+        movea(buf, -(DUMP_LEN as i16), SLOT_REG, SLOT_REG);
+        movea(buf, (DUMP_LEN / 4) as i16, 0, COUNTER_REG);
+        mov(buf, 0, DUMP_SUM_REG);
+        ld_w(buf, 0, SLOT_REG, VALUE_REG);
+        add(buf, VALUE_REG, DUMP_SUM_REG);
+        add_imm(buf, 4, SLOT_REG);
+        add_imm(buf, -1, COUNTER_REG);
+        bcond(buf, COND_NZ, -10);
+
+        ori(buf, CHCW_CLEAR_ALL, 0, CHCW_REG);
+        ldsr(buf, CHCW_REG, CHCW);
+        ldsr(buf, 0, CHCW);
+
+        // These hold addrs and leftovers rather than results
+        for &reg in [SLOT_REG, VALUE_REG, CHCW_REG, LINK_REG].iter() {
+            mov(buf, 0, reg);
+        }
+    }
+}
+
+// CHCW_REG = the dump area's addr (as SA) with the given bits
+fn write_dump_addr(buf: &mut Vec<u8>, bits: u16) {
+    movhi(buf, DUMP_ADDR_HI, 0, CHCW_REG);
+    movea(buf, (DUMP_ADDR_LO | bits) as i16, CHCW_REG, CHCW_REG);
+}
@@ -4,12 +4,29 @@ use transport::{self, exchange_packet};
 use std::io::{Read, Write};
 use std::mem::transmute;
 
+// Must match the execute harness
+pub const NUM_INITIAL_REGS: usize = 32; // r0-r31 (r0's is ignored, of course)
+pub const NUM_RESULT_WORDS: usize = 35; // r0-r31, psw, status, elapsed ticks
+pub const WATCHDOG_MS: u32 = 500; // watchdogReload timer ticks of 20us each
+
+// A try the loader doesn't answer lets at least this much time pass on the VB's side. The emulated port runs its VB
+//  for 10ms after a failed handshake, while a real link waits out its serial timeout (1s).
+const MIN_FAILED_TRY_MS: u32 = 10;
+// On top of the time spent busy, for the odd corrupted exchange
+const EXTRA_TRIES: usize = 20;
+
+// How many tries it takes to wait out a loader that's busy executing for up to busy_ms
+fn tries_while_busy(busy_ms: u32) -> usize {
+    (busy_ms / MIN_FAILED_TRY_MS) as usize + EXTRA_TRIES
+}
+
 #[derive(Debug)]
 pub enum Error {
     Transport(transport::Error),
     DataEmpty,
     DataTooLarge,
     ZeroLength,
+    WrongNumInitialRegs,
     ProtocolViolation,
     WrongCrapsum(Crapsum),
     InvalidResponse(Vec<u8>),
@@ -21,6 +38,10 @@ enum Command {
     ReadMemRegion { addr: u32, length: u32 },
     ReadMemRegionData,
     Execute { entry: u32 },
+    RunTest { entry: u32, length: u32, initial_regs: Vec<u32> },
+    RunTestData { data: Vec<u8> },
+    RunTestResult,
+    ExecuteBatch { table: u32 },
 }
 
 #[derive(Eq, PartialEq)]
@@ -28,6 +49,7 @@ enum Response {
     UnexpectedCommand,
     OkWithCrapsum(Crapsum),
     ReadMemRegionData(Vec<u8>),
+    RunTestResult(Vec<u32>),
 }
 
 impl Response {
@@ -64,6 +86,23 @@ impl Response {
 
                 Ok(Response::ReadMemRegionData(data[1..].iter().cloned().collect()))
             }
+            0x03 => {
+                if data.len() != 1 + NUM_RESULT_WORDS * 4 {
+                    return Err(Error::InvalidResponse(data));
+                }
+
+                let mut words = Vec::new();
+                for i in 0..NUM_RESULT_WORDS {
+                    let mut word = 0;
+                    for j in 0..4 {
+                        word >>= 8;
+                        word |= (data[1 + i * 4 + j] as u32) << 24;
+                    }
+                    words.push(word);
+                }
+
+                Ok(Response::RunTestResult(words))
+            }
             _ => Err(Error::InvalidResponse(data))
         }
     }
@@ -193,6 +232,11 @@ pub fn read_mem_region<P: Read + Write>(port: &mut P, addr: u32, length: u32) ->
     Ok(ret)
 }
 
+pub fn check_status<P: Read + Write>(port: &mut P) -> Result<(), Error> {
+    let (response, expected_crapsum) = issue_command(port, Command::CheckStatus)?;
+    expect_ok_with_crapsum(response, expected_crapsum)
+}
+
 pub fn execute<P: Read + Write>(port: &mut P, entry: u32) -> Result<(), Error> {
     let (response, expected_crapsum) = issue_command(port, Command::Execute { entry: entry })?;
 
@@ -233,6 +277,103 @@ pub fn execute<P: Read + Write>(port: &mut P, entry: u32) -> Result<(), Error> {
     Ok(())
 }
 
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ExecuteResult {
+    // regs contains r0-r31 followed by psw; elapsed_ticks is in 20us hardware timer ticks
+    Completed { regs: Vec<u32>, elapsed_ticks: u32 },
+    TimedOut,
+}
+
+impl ExecuteResult {
+    // Parses a harness result block (NUM_RESULT_WORDS words)
+    pub fn from_result_words(mut words: Vec<u32>) -> Result<ExecuteResult, Error> {
+        if words.len() != NUM_RESULT_WORDS {
+            return Err(Error::ProtocolViolation);
+        }
+
+        let elapsed_ticks = words.pop().unwrap();
+        let status = words.pop().unwrap();
+        match status {
+            0 => Ok(ExecuteResult::Completed { regs: words, elapsed_ticks: elapsed_ticks }),
+            1 => Ok(ExecuteResult::TimedOut),
+            _ => Err(Error::ProtocolViolation),
+        }
+    }
+}
+
+pub fn run_test<P: Read + Write>(port: &mut P, entry: u32, rom: &[u8], initial_regs: &[u32]) -> Result<ExecuteResult, Error> {
+    if rom.is_empty() {
+        return Err(Error::DataEmpty);
+    }
+
+    if initial_regs.len() != NUM_INITIAL_REGS {
+        return Err(Error::WrongNumInitialRegs);
+    }
+
+    let (response, expected_crapsum) = issue_command(port, Command::RunTest { entry: entry, length: rom.len() as u32, initial_regs: initial_regs.to_vec() })?;
+    expect_ok_with_crapsum(response, expected_crapsum)?;
+
+    // The loader acks each data packet directly, so no status checks are needed in between
+    for data in rom.chunks(256 - 1) {
+        let (response, expected_crapsum) = issue_command(port, Command::RunTestData { data: data.to_vec() })?;
+        expect_ok_with_crapsum(response, expected_crapsum)?;
+    }
+
+    // The harness watchdog bounds how long the loader can be busy executing
+    let max_result_tries = tries_while_busy(WATCHDOG_MS);
+    let mut result_tries = 0;
+    loop {
+        if let Ok((response, _)) = issue_command(port, Command::RunTestResult) {
+            match response {
+                Response::RunTestResult(words) => {
+                    return ExecuteResult::from_result_words(words);
+                }
+                _ => {
+                    return Err(Error::ProtocolViolation);
+                }
+            }
+        }
+
+        result_tries += 1;
+        if result_tries >= max_result_tries {
+            return Err(Error::ProtocolViolation);
+        }
+    }
+}
+
+pub fn execute_batch<P: Read + Write>(port: &mut P, table: u32, num_cases: usize) -> Result<(), Error> {
+    let (response, expected_crapsum) = issue_command(port, Command::ExecuteBatch { table: table })?;
+    expect_ok_with_crapsum(response, expected_crapsum)?;
+
+    // Every case can run until the harness watchdog fires, so a batch that times out all the way through keeps the
+    //  loader busy for that long
+    let max_status_tries = tries_while_busy(WATCHDOG_MS * num_cases as u32);
+    let mut status_tries = 0;
+    loop {
+        if let Ok((response, expected_crapsum)) = issue_command(port, Command::CheckStatus) {
+            return expect_ok_with_crapsum(response, expected_crapsum);
+        }
+
+        status_tries += 1;
+        if status_tries >= max_status_tries {
+            return Err(Error::ProtocolViolation);
+        }
+    }
+}
+
+fn expect_ok_with_crapsum(response: Response, expected_crapsum: Crapsum) -> Result<(), Error> {
+    match response {
+        Response::OkWithCrapsum(crapsum) => {
+            if crapsum != expected_crapsum {
+                return Err(Error::WrongCrapsum(crapsum));
+            }
+
+            Ok(())
+        }
+        _ => Err(Error::ProtocolViolation),
+    }
+}
+
 fn issue_command<P: Read + Write>(port: &mut P, command: Command) -> Result<(Response, Crapsum), Error> {
     let packet = match command {
         Command::CheckStatus => vec![0x00],
@@ -275,8 +416,65 @@ fn issue_command<P: Read + Write>(port: &mut P, command: Command) -> Result<(Res
                 .cloned()
                 .collect::<Vec<_>>()
         }
+        Command::RunTest { entry, length, initial_regs } => {
+            let entry_bytes: [u8; 4] = unsafe { transmute(entry.to_le()) };
+            let length_bytes: [u8; 4] = unsafe { transmute(length.to_le()) };
+            let initial_regs_bytes = initial_regs.iter().flat_map(|reg| {
+                let reg_bytes: [u8; 4] = unsafe { transmute(reg.to_le()) };
+                reg_bytes.to_vec()
+            }).collect::<Vec<_>>();
+
+            [0x05].iter()
+                .chain(entry_bytes.iter())
+                .chain(length_bytes.iter())
+                .chain(initial_regs_bytes.iter())
+                .cloned()
+                .collect::<Vec<_>>()
+        }
+        Command::RunTestData { data } => {
+            if data.is_empty() {
+                return Err(Error::DataEmpty);
+            }
+
+            if data.len() > 256 - 1 {
+                return Err(Error::DataTooLarge);
+            }
+
+            [0x06].iter()
+                .chain(data.iter())
+                .cloned()
+                .collect::<Vec<_>>()
+        }
+        Command::RunTestResult => vec![0x07],
+        Command::ExecuteBatch { table } => {
+            let table_bytes: [u8; 4] = unsafe { transmute(table.to_le()) };
+
+            [0x08].iter()
+                .chain(table_bytes.iter())
+                .cloned()
+                .collect::<Vec<_>>()
+        }
     };
     let packet_crapsum = Crapsum::compute(&packet);
     let received_packet = exchange_packet(port, &packet).map_err(|e| Error::Transport(e))?;
     Response::parse(received_packet).map(|response| (response, packet_crapsum))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asm;
+    use emu::EmulatedVbSerialPort;
+    use placement::DEFAULT_ROM_ADDR;
+
+    #[test]
+    fn hung_tests_time_out_on_the_emulator() {
+        let mut port = EmulatedVbSerialPort::new();
+
+        let mut rom = Vec::new();
+        asm::bcond(&mut rom, asm::COND_ALWAYS, 0);
+
+        let result = run_test(&mut port, DEFAULT_ROM_ADDR, &rom, &[0; NUM_INITIAL_REGS]).unwrap();
+        assert_eq!(result, ExecuteResult::TimedOut);
+    }
+}
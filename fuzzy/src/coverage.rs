@@ -0,0 +1,335 @@
+use command::ExecuteResult;
+use disasm::{self, Format, Imm, Instruction, Opcode, OPCODES, PSW_REG_ID};
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+const REG_CLASSES: &'static [&'static str] = &["r0 src", "r0 dst", "same", "distinct"];
+const IMM5_REG_CLASSES: &'static [&'static str] = &["r0 dst", "distinct"];
+
+const SIGNED_IMM_RANGES: &'static [&'static str] = &["zero", "one", "minus one", "small +", "small -", "large +", "large -", "max", "min"];
+const UNSIGNED_IMM_RANGES: &'static [&'static str] = &["zero", "one", "small", "large", "max"];
+
+const FLAG_NAMES: &'static [(u32, &'static str)] = &[
+    (disasm::FLAG_Z, "Z"),
+    (disasm::FLAG_S, "S"),
+    (disasm::FLAG_OV, "OV"),
+    (disasm::FLAG_CY, "CY"),
+];
+
+// Tracks what the generated programs actually exercised. Everything except flag outcomes comes from decoding the
+//  roms; flag outcomes come from the reference target's (normally the emulator's) results.
+pub struct Coverage {
+    reference_target: String,
+
+    num_programs: u64,
+    num_straight_line_programs: u64,
+
+    opcodes: BTreeMap<&'static str, u64>,
+    conditions: BTreeMap<(&'static str, i32), u64>,
+    reg_classes: BTreeMap<(&'static str, &'static str), u64>,
+    imm_ranges: BTreeMap<(&'static str, &'static str), u64>,
+    flag_outcomes: BTreeMap<(&'static str, u32), u64>,
+}
+
+impl Coverage {
+    pub fn new<N: Into<String>>(reference_target: N) -> Coverage {
+        Coverage {
+            reference_target: reference_target.into(),
+
+            num_programs: 0,
+            num_straight_line_programs: 0,
+
+            opcodes: BTreeMap::new(),
+            conditions: BTreeMap::new(),
+            reg_classes: BTreeMap::new(),
+            imm_ranges: BTreeMap::new(),
+            flag_outcomes: BTreeMap::new(),
+        }
+    }
+
+    pub fn reference_target(&self) -> &str {
+        &self.reference_target
+    }
+
+    pub fn record(&mut self, rom: &[u8], result: Option<&ExecuteResult>) {
+        self.num_programs += 1;
+
+        let instructions = program_instructions(rom);
+
+        for instruction in instructions.iter() {
+            let opcode = match instruction.opcode {
+                Some(opcode) => opcode,
+                _ => continue,
+            };
+
+            *self.opcodes.entry(opcode.name).or_insert(0) += 1;
+
+            match opcode.name {
+                "setf" | "bcond" => *self.conditions.entry((opcode.name, instruction.imm)).or_insert(0) += 1,
+                _ => (),
+            }
+
+            if let Some(reg_class) = reg_class(opcode, instruction) {
+                *self.reg_classes.entry((opcode.name, reg_class)).or_insert(0) += 1;
+            }
+
+            if let Some(imm_range) = imm_range(opcode, instruction) {
+                *self.imm_ranges.entry((opcode.name, imm_range)).or_insert(0) += 1;
+            }
+        }
+
+        if let Some(&ExecuteResult::Completed { ref regs, .. }) = result {
+            for (name, flags) in flag_observations(&instructions, regs) {
+                *self.flag_outcomes.entry((name, flags)).or_insert(0) += 1;
+            }
+            if is_straight_line(&instructions) {
+                self.num_straight_line_programs += 1;
+            }
+        }
+    }
+
+    // (name, covered, total) for opcodes, reg classes, imm ranges and flag outcomes
+    pub fn totals(&self) -> Vec<(&'static str, usize, usize)> {
+        let mut opcodes = (0, 0);
+        let mut reg_classes = (0, 0);
+        let mut imm_ranges = (0, 0);
+        let mut flag_outcomes = (0, 0);
+
+        for opcode in OPCODES.iter() {
+            opcodes.1 += 1;
+            if self.opcodes.contains_key(opcode.name) {
+                opcodes.0 += 1;
+            }
+
+            for &class in expected_reg_classes(opcode) {
+                reg_classes.1 += 1;
+                if self.reg_classes.contains_key(&(opcode.name, class)) {
+                    reg_classes.0 += 1;
+                }
+            }
+
+            for &range in expected_imm_ranges(opcode) {
+                imm_ranges.1 += 1;
+                if self.imm_ranges.contains_key(&(opcode.name, range)) {
+                    imm_ranges.0 += 1;
+                }
+            }
+
+            for flags in possible_flag_outcomes(opcode) {
+                flag_outcomes.1 += 1;
+                if self.flag_outcomes.contains_key(&(opcode.name, flags)) {
+                    flag_outcomes.0 += 1;
+                }
+            }
+        }
+
+        vec![
+            ("opcodes", opcodes.0, opcodes.1),
+            ("reg classes", reg_classes.0, reg_classes.1),
+            ("imm ranges", imm_ranges.0, imm_ranges.1),
+            ("flag outcomes", flag_outcomes.0, flag_outcomes.1),
+        ]
+    }
+
+    pub fn summary(&self) -> String {
+        let totals = self.totals().iter()
+            .map(|&(name, covered, total)| format!("{}/{} {}", covered, total, name))
+            .collect::<Vec<_>>();
+        format!("Coverage: {}", totals.join(", "))
+    }
+
+    pub fn report(&self) -> String {
+        let mut ret = String::new();
+
+        writeln!(ret, "{} programs ({} straight-line with flag outcomes from `{}`)", self.num_programs, self.num_straight_line_programs, self.reference_target).unwrap();
+        writeln!(ret, "{}", self.summary()).unwrap();
+        writeln!(ret, "").unwrap();
+        writeln!(ret, "Gaps are marked with `!!`").unwrap();
+
+        for opcode in OPCODES.iter() {
+            writeln!(ret, "").unwrap();
+
+            let count = self.opcodes.get(opcode.name).cloned().unwrap_or(0);
+            writeln!(ret, "{}{}: {}", gap_marker(count), opcode.name, count).unwrap();
+
+            if opcode.name == "setf" || opcode.name == "bcond" {
+                let counts = (0..16).map(|cond| (cond_name(cond), self.conditions.get(&(opcode.name, cond)).cloned().unwrap_or(0))).collect::<Vec<_>>();
+                write_counts(&mut ret, "conditions", &counts);
+            }
+
+            let classes = expected_reg_classes(opcode);
+            if !classes.is_empty() {
+                let counts = classes.iter().map(|&class| (class.to_string(), self.reg_classes.get(&(opcode.name, class)).cloned().unwrap_or(0))).collect::<Vec<_>>();
+                write_counts(&mut ret, "regs", &counts);
+            }
+
+            let ranges = expected_imm_ranges(opcode);
+            if !ranges.is_empty() {
+                let counts = ranges.iter().map(|&range| (range.to_string(), self.imm_ranges.get(&(opcode.name, range)).cloned().unwrap_or(0))).collect::<Vec<_>>();
+                write_counts(&mut ret, "imm", &counts);
+            }
+
+            let outcomes = possible_flag_outcomes(opcode);
+            if !outcomes.is_empty() {
+                let counts = outcomes.iter().map(|&flags| (flags_name(flags), self.flag_outcomes.get(&(opcode.name, flags)).cloned().unwrap_or(0))).collect::<Vec<_>>();
+                write_counts(&mut ret, "flags", &counts);
+            }
+        }
+
+        ret
+    }
+}
+
+fn write_counts(report: &mut String, label: &str, counts: &[(String, u64)]) {
+    let counts = counts.iter().map(|&(ref name, count)| format!("{}{} {}", gap_marker(count), name, count)).collect::<Vec<_>>();
+    writeln!(report, "    {}: {}", label, counts.join(", ")).unwrap();
+}
+
+fn gap_marker(count: u64) -> &'static str {
+    if count == 0 { "!!" } else { "" }
+}
+
+// Everything up to (and including) the final ret (trap 0); the harness never gets past it
+pub fn program_instructions(rom: &[u8]) -> Vec<Instruction> {
+    let mut instructions = disasm::decode(rom);
+    if let Some(ret_index) = instructions.iter().rposition(|instruction| instruction.name() == "trap") {
+        instructions.truncate(ret_index + 1);
+    }
+    instructions
+}
+
+fn is_straight_line(instructions: &[Instruction]) -> bool {
+    match instructions.split_last() {
+        Some((_, body)) => !body.iter().any(|instruction| instruction.is_branch()),
+        _ => false,
+    }
+}
+
+// Flags are only visible in the final psw and in regs last written by `stsr psw`, and we can only attribute them to
+//  an instruction when the program runs straight through.
+pub fn flag_observations(instructions: &[Instruction], regs: &[u32]) -> Vec<(&'static str, u32)> {
+    let mut ret = Vec::new();
+
+    if !is_straight_line(instructions) {
+        return ret;
+    }
+    let body = &instructions[..instructions.len() - 1];
+
+    let mut last_flag_op: Option<&'static Opcode> = None;
+    // For each reg, the flag-setting op whose flags it holds (if it was last written by `stsr psw`)
+    let mut psw_snapshots: Vec<Option<&'static Opcode>> = vec![None; 32];
+
+    for instruction in body.iter() {
+        let opcode = match instruction.opcode {
+            Some(opcode) => opcode,
+            _ => return Vec::new(),
+        };
+
+        for reg in instruction.dest_regs() {
+            psw_snapshots[reg as usize] = if opcode.name == "stsr" && instruction.imm == PSW_REG_ID as i32 { last_flag_op } else { None };
+        }
+
+        if opcode.flags != 0 {
+            last_flag_op = Some(opcode);
+        }
+        // The flags come from a reg now, not from any op
+        if opcode.name == "ldsr" && instruction.imm == PSW_REG_ID as i32 {
+            last_flag_op = None;
+        }
+    }
+
+    for (reg, snapshot) in psw_snapshots.iter().enumerate() {
+        if let &Some(opcode) = snapshot {
+            ret.push((opcode.name, regs[reg] & opcode.flags));
+        }
+    }
+
+    // The result's psw follows r0-r31
+    if let (Some(opcode), Some(&psw)) = (last_flag_op, regs.get(32)) {
+        ret.push((opcode.name, psw & opcode.flags));
+    }
+
+    ret
+}
+
+fn reg_class(opcode: &Opcode, instruction: &Instruction) -> Option<&'static str> {
+    match opcode.format {
+        Format::I | Format::V | Format::VII if opcode.name != "jmp" => Some(if instruction.reg1 == instruction.reg2 {
+            "same"
+        } else if instruction.reg1 == 0 {
+            "r0 src"
+        } else if instruction.reg2 == 0 {
+            "r0 dst"
+        } else {
+            "distinct"
+        }),
+        Format::II if opcode.name != "trap" => Some(if instruction.reg2 == 0 { "r0 dst" } else { "distinct" }),
+        _ => None,
+    }
+}
+
+fn expected_reg_classes(opcode: &Opcode) -> &'static [&'static str] {
+    match opcode.format {
+        Format::I | Format::V | Format::VII if opcode.name != "jmp" => REG_CLASSES,
+        Format::II if opcode.name != "trap" => IMM5_REG_CLASSES,
+        _ => &[],
+    }
+}
+
+fn imm_range(opcode: &Opcode, instruction: &Instruction) -> Option<&'static str> {
+    let (max, min) = match (opcode.format, opcode.imm) {
+        (Format::II, Imm::Signed) => (15, -16),
+        (Format::V, Imm::Signed) => (0x7fff, -0x8000),
+        (Format::II, Imm::Unsigned) => (31, 0),
+        (Format::V, Imm::Unsigned) => (0xffff, 0),
+        _ => return None,
+    };
+    // Small means it fits in a nibble
+    let imm = instruction.imm;
+    Some(match imm {
+        0 => "zero",
+        1 => "one",
+        -1 => "minus one",
+        _ if imm == max => "max",
+        _ if imm == min && min != 0 => "min",
+        _ if imm > 0 && imm < 16 && opcode.imm == Imm::Unsigned => "small",
+        _ if imm > 0 && imm < 16 => "small +",
+        _ if imm < 0 && imm > -16 => "small -",
+        _ if imm > 0 && opcode.imm == Imm::Unsigned => "large",
+        _ if imm > 0 => "large +",
+        _ => "large -",
+    })
+}
+
+fn expected_imm_ranges(opcode: &Opcode) -> &'static [&'static str] {
+    match (opcode.format, opcode.imm) {
+        // imm5 is too narrow for "large" values in between small and the extremes
+        (Format::II, Imm::Signed) => &["zero", "one", "minus one", "small +", "small -", "max", "min"],
+        (Format::II, Imm::Unsigned) => &["zero", "one", "small", "large", "max"],
+        (Format::V, Imm::Signed) => SIGNED_IMM_RANGES,
+        (Format::V, Imm::Unsigned) => UNSIGNED_IMM_RANGES,
+        _ => &[],
+    }
+}
+
+// Every combination of the flags the op can set, except Z and S together (a zero result can't be negative)
+fn possible_flag_outcomes(opcode: &Opcode) -> Vec<u32> {
+    (0..16)
+        .filter(|&flags| flags & !opcode.flags == 0 && opcode.flags != 0)
+        .filter(|&flags| flags & (disasm::FLAG_Z | disasm::FLAG_S) != (disasm::FLAG_Z | disasm::FLAG_S))
+        .collect()
+}
+
+fn flags_name(flags: u32) -> String {
+    let names = FLAG_NAMES.iter().filter(|&&(flag, _)| flags & flag != 0).map(|&(_, name)| name).collect::<Vec<_>>();
+    if names.is_empty() {
+        String::from("-")
+    } else {
+        names.join("+")
+    }
+}
+
+fn cond_name(cond: i32) -> String {
+    disasm::COND_NAMES[cond as usize].into()
+}
@@ -0,0 +1,361 @@
+use rand::{Rng, StdRng};
+
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+
+use generators::Generator;
+use values::ValueStrategy;
+
+// Regs with less entropy than this are considered collapsed to a constant
+const COLLAPSED_ENTROPY: u32 = 8;
+// Once this many regs have collapsed, start refreshing them from live regs
+const MAX_COLLAPSED_REGS: usize = 8;
+
+#[derive(Clone, Copy)]
+struct RegState {
+    // Rough number of bits that still depend on the initial regs. This is only an estimate; it's good enough to tell
+    //  regs carrying data apart from regs that have been overwritten with constants.
+    entropy: u32,
+    // Written but not yet read, so overwriting it would throw away a result before it reaches the final state
+    pending: bool,
+    last_write: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Form {
+    // reg2 = reg2 op reg1
+    ReadModifyWrite { self_cancels: bool, writes_r30: bool },
+    // reg2 = f(reg1)
+    Move { entropy_cap: u32 },
+    // reg2 = reg2 op imm5
+    ModifyImm { shift: bool },
+    // reg2 = something that doesn't depend on any reg
+    Load { entropy: u32 },
+    // reg2 = f(reg2)
+    InPlace,
+    // Only sets flags
+    Compare { has_reg1: bool },
+    // Only writes the return addr to r31 (jal)
+    Link,
+    Unknown,
+}
+
+// Wraps another generator and rewrites the register operands of each instruction it produces, so that results feed
+//  into later instructions instead of being overwritten before anything sees them. Sources prefer the most recent
+//  results (building dependency chains), destinations prefer regs whose values have already been used, and regs that
+//  have collapsed to constants are refreshed from live ones.
+pub struct DataflowGenerator {
+    inner: Box<Generator>,
+    rng: StdRng,
+    values: ValueStrategy,
+
+    regs: [RegState; 32],
+    instruction_index: usize,
+}
+
+impl DataflowGenerator {
+    pub fn new(inner: Box<Generator>, rng: StdRng, values: ValueStrategy) -> DataflowGenerator {
+        let mut regs = [RegState { entropy: 32, pending: false, last_write: 0 }; 32];
+        // r0 is hardwired
+        regs[0].entropy = 0;
+
+        DataflowGenerator {
+            inner: inner,
+            rng: rng,
+            values: values,
+
+            regs: regs,
+            instruction_index: 0,
+        }
+    }
+
+    fn pick_source(&mut self, exclude: Option<u32>) -> u32 {
+        if self.rng.gen() {
+            let most_recent = (1..32)
+                .filter(|&reg| Some(reg) != exclude && self.regs[reg as usize].pending)
+                .max_by_key(|&reg| self.regs[reg as usize].last_write);
+            if let Some(reg) = most_recent {
+                return reg;
+            }
+        }
+
+        let live = (1..32)
+            .filter(|&reg| Some(reg) != exclude && self.regs[reg as usize].entropy >= COLLAPSED_ENTROPY)
+            .collect::<Vec<_>>();
+        self.pick_from(live, exclude)
+    }
+
+    fn pick_dest(&mut self, exclude: Option<u32>) -> u32 {
+        // Regs whose values have already been used can be overwritten without losing anything
+        let free = (1..32)
+            .filter(|&reg| Some(reg) != exclude && !self.regs[reg as usize].pending)
+            .collect::<Vec<_>>();
+        self.pick_from(free, exclude)
+    }
+
+    fn pick_from(&mut self, candidates: Vec<u32>, exclude: Option<u32>) -> u32 {
+        if !candidates.is_empty() {
+            return candidates[self.rng.gen::<usize>() % candidates.len()];
+        }
+
+        loop {
+            let reg = self.rng.gen::<u32>() % 31 + 1;
+            if Some(reg) != exclude {
+                return reg;
+            }
+        }
+    }
+
+    fn read(&mut self, reg: u32) {
+        self.regs[reg as usize].pending = false;
+    }
+
+    fn write(&mut self, reg: u32, entropy: u32) {
+        if reg == 0 {
+            return;
+        }
+
+        let state = &mut self.regs[reg as usize];
+        state.entropy = entropy;
+        state.pending = true;
+        state.last_write = self.instruction_index;
+    }
+
+    fn entropy(&self, reg: u32) -> u32 {
+        self.regs[reg as usize].entropy
+    }
+
+    fn refresh_collapsed_reg(&mut self, buf: &mut Vec<u8>) {
+        let collapsed = (1..32).filter(|&reg| self.regs[reg as usize].entropy < COLLAPSED_ENTROPY).collect::<Vec<_>>();
+        if collapsed.len() < MAX_COLLAPSED_REGS {
+            return;
+        }
+
+        let reg2 = collapsed[self.rng.gen::<usize>() % collapsed.len()];
+        let reg1 = self.pick_source(Some(reg2));
+
+        // movea imm16, reg1, reg2
+        let op = 0b101000;
+        let imm16 = self.values.gen_bits(&mut self.rng, 16) as u16;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+        buf.write_u16::<LittleEndian>(imm16).unwrap();
+
+        let entropy = self.entropy(reg1);
+        self.read(reg1);
+        self.write(reg2, entropy);
+        self.instruction_index += 1;
+    }
+
+    fn rewrite(&mut self, instruction: &mut [u8]) {
+        let first_halfword = LittleEndian::read_u16(&instruction[0..2]);
+        let op = first_halfword >> 10;
+        let form = classify(op, if instruction.len() >= 4 { LittleEndian::read_u16(&instruction[2..4]) >> 10 } else { 0 });
+        let orig_reg1 = (first_halfword & 0x1f) as u32;
+        let orig_reg2 = ((first_halfword >> 5) & 0x1f) as u32;
+        let imm5 = orig_reg1;
+
+        let (reg1, reg2) = match form {
+            Form::ReadModifyWrite { self_cancels, writes_r30 } => {
+                let reg2 = self.pick_source(None);
+                let reg1 = self.pick_source(Some(reg2));
+                let entropy = if self_cancels && reg1 == reg2 { 0 } else { self.entropy(reg1).max(self.entropy(reg2)) };
+                self.read(reg1);
+                self.read(reg2);
+                if writes_r30 {
+                    self.write(30, entropy);
+                }
+                self.write(reg2, entropy);
+                (reg1, reg2)
+            }
+            Form::Move { entropy_cap } => {
+                let reg1 = self.pick_source(None);
+                let reg2 = self.pick_dest(Some(reg1));
+                let entropy = self.entropy(reg1).min(entropy_cap);
+                self.read(reg1);
+                self.write(reg2, entropy);
+                (reg1, reg2)
+            }
+            Form::ModifyImm { shift } => {
+                let reg2 = self.pick_source(None);
+                let entropy = if shift { self.entropy(reg2).saturating_sub(imm5) } else { self.entropy(reg2) };
+                self.read(reg2);
+                self.write(reg2, entropy);
+                (imm5, reg2)
+            }
+            Form::Load { entropy } => {
+                let reg2 = self.pick_dest(None);
+                self.write(reg2, entropy);
+                (orig_reg1, reg2)
+            }
+            Form::InPlace => {
+                let reg2 = self.pick_source(None);
+                let entropy = self.entropy(reg2);
+                self.read(reg2);
+                self.write(reg2, entropy);
+                (orig_reg1, reg2)
+            }
+            Form::Compare { has_reg1 } => {
+                let reg2 = self.pick_source(None);
+                let reg1 = if has_reg1 { self.pick_source(Some(reg2)) } else { imm5 };
+                self.read(reg2);
+                if has_reg1 {
+                    self.read(reg1);
+                }
+                (reg1, reg2)
+            }
+            Form::Link => {
+                self.write(31, 0);
+                (orig_reg1, orig_reg2)
+            }
+            Form::Unknown => (orig_reg1, orig_reg2),
+        };
+
+        let first_halfword = (first_halfword & 0xfc00) | ((reg2 as u16) << 5) | (reg1 as u16);
+        LittleEndian::write_u16(&mut instruction[0..2], first_halfword);
+
+        self.instruction_index += 1;
+    }
+}
+
+impl Generator for DataflowGenerator {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        self.refresh_collapsed_reg(buf);
+
+        let start = buf.len();
+        self.inner.next(buf);
+
+        let mut offset = start;
+        while offset + 2 <= buf.len() {
+            let op = LittleEndian::read_u16(&buf[offset..offset + 2]) >> 10;
+            let len = instruction_len(op);
+            if offset + len > buf.len() {
+                break;
+            }
+            self.rewrite(&mut buf[offset..offset + len]);
+            offset += len;
+        }
+    }
+}
+
+fn instruction_len(op: u16) -> usize {
+    // Formats III (bcond) and I/II are 16 bits; everything from 0b101000 up is 32 bits
+    if op >= 0b101000 { 4 } else { 2 }
+}
+
+fn classify(op: u16, subop: u16) -> Form {
+    match op {
+        // add, sub, and, or, xor
+        0b000001 | 0b001101 | 0b001100 => Form::ReadModifyWrite { self_cancels: false, writes_r30: false },
+        0b000010 | 0b001110 => Form::ReadModifyWrite { self_cancels: true, writes_r30: false },
+        // shl, shr, sar (reg)
+        0b000100 | 0b000101 | 0b000111 => Form::ReadModifyWrite { self_cancels: false, writes_r30: false },
+        // mul, div, mulu, divu
+        0b001000 | 0b001001 | 0b001010 | 0b001011 => Form::ReadModifyWrite { self_cancels: false, writes_r30: true },
+        // mov, not (reg)
+        0b000000 | 0b001111 => Form::Move { entropy_cap: 32 },
+        // movea, movhi, addi, ori, xori
+        0b101000 | 0b101111 | 0b101001 | 0b101100 | 0b101110 => Form::Move { entropy_cap: 32 },
+        // andi clears the top half
+        0b101101 => Form::Move { entropy_cap: 16 },
+        // add (imm)
+        0b010001 => Form::ModifyImm { shift: false },
+        // shl, shr, sar (imm)
+        0b010100 | 0b010101 | 0b010111 => Form::ModifyImm { shift: true },
+        // mov (imm)
+        0b010000 => Form::Load { entropy: 0 },
+        // setf
+        0b010010 => Form::Load { entropy: 1 },
+        // stsr (psw)
+        0b011101 => Form::Load { entropy: 4 },
+        // cmp (reg), cmp (imm)
+        0b000011 => Form::Compare { has_reg1: true },
+        0b010011 => Form::Compare { has_reg1: false },
+        // jal
+        0b101011 => Form::Link,
+        // Format VII
+        0b111110 => match subop {
+            // mpyhw
+            0b001100 => Form::ReadModifyWrite { self_cancels: false, writes_r30: false },
+            // rev
+            0b001010 => Form::Move { entropy_cap: 32 },
+            // xb, xh
+            0b001000 | 0b001001 => Form::InPlace,
+            _ => Form::Unknown,
+        },
+        _ => Form::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generators::{build_all_generator, build_rng, Mix};
+
+    // Returns how many of the regs picked as destinations were overwritten before anything read them, and how many
+    //  destinations there were. Values still around at the end make it into the final dump, so they count as used.
+    //  r30 (mul/div) and r31 (jal) are written implicitly, so there's no picking those.
+    fn dead_dests(rom: &[u8]) -> (usize, usize) {
+        let mut pending = [false; 32];
+        let mut dead = 0;
+        let mut dests = 0;
+
+        let mut offset = 0;
+        while offset + 2 <= rom.len() {
+            let first_halfword = LittleEndian::read_u16(&rom[offset..offset + 2]);
+            let op = first_halfword >> 10;
+            let len = instruction_len(op);
+            let subop = if len == 4 { LittleEndian::read_u16(&rom[offset + 2..offset + 4]) >> 10 } else { 0 };
+            let reg1 = (first_halfword & 0x1f) as usize;
+            let reg2 = ((first_halfword >> 5) & 0x1f) as usize;
+            offset += len;
+
+            let (reads_reg1, reads_reg2, writes_reg2) = match classify(op, subop) {
+                Form::ReadModifyWrite { .. } => (true, true, true),
+                Form::Move { .. } => (true, false, true),
+                Form::ModifyImm { .. } | Form::InPlace => (false, true, true),
+                Form::Load { .. } => (false, false, true),
+                Form::Compare { has_reg1 } => (has_reg1, true, false),
+                Form::Link | Form::Unknown => (false, false, false),
+            };
+            if reads_reg1 {
+                pending[reg1] = false;
+            }
+            if reads_reg2 {
+                pending[reg2] = false;
+            }
+            if writes_reg2 && reg2 != 0 {
+                if pending[reg2] {
+                    dead += 1;
+                }
+                pending[reg2] = true;
+                dests += 1;
+            }
+        }
+
+        (dead, dests)
+    }
+
+    fn generate(gen: &mut Generator) -> Vec<u8> {
+        let mut rom = Vec::new();
+        for _ in 0..4000 {
+            gen.next(&mut rom);
+        }
+        rom
+    }
+
+    #[test]
+    fn results_are_read_or_survive() {
+        for seed in 0..8 {
+            let mut rng = build_rng(seed);
+            let all = build_all_generator(&mut rng, &Mix::uniform(), ValueStrategy::Uniform);
+            let mut dataflow = DataflowGenerator::new(Box::new(all), build_rng(rng.gen::<usize>()), ValueStrategy::Uniform);
+            let (dead, dests) = dead_dests(&generate(&mut dataflow));
+
+            let mut all = build_all_generator(&mut rng, &Mix::uniform(), ValueStrategy::Uniform);
+            let (all_dead, all_dests) = dead_dests(&generate(&mut all));
+
+            // Once every reg holds an unread result, something has to give, so a few are lost
+            assert!(dead * 10 < dests, "seed {}: {} of {} results lost", seed, dead, dests);
+            assert!(dead * all_dests * 2 < all_dead * dests, "seed {}: {}/{} lost vs {}/{} without dataflow", seed, dead, dests, all_dead, all_dests);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    // reg1, reg2
+    I,
+    // imm5, reg2
+    II,
+    // cond, disp9
+    III,
+    // disp26
+    IV,
+    // imm16, reg1, reg2
+    V,
+    // disp16, reg1 (base), reg2
+    VI,
+    // reg1, reg2, subop
+    VII,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Imm {
+    None,
+    Signed,
+    Unsigned,
+}
+
+pub struct Opcode {
+    pub name: &'static str,
+    pub op: u16,
+    pub subop: Option<u16>,
+    pub format: Format,
+    pub imm: Imm,
+    // PSW flags (Z, S, OV, CY in bits 0-3) this op can set; 0 for ops that leave the flags alone
+    pub flags: u32,
+}
+
+pub const FLAG_Z: u32 = 1 << 0;
+pub const FLAG_S: u32 = 1 << 1;
+pub const FLAG_OV: u32 = 1 << 2;
+pub const FLAG_CY: u32 = 1 << 3;
+
+const ARITH_FLAGS: u32 = FLAG_Z | FLAG_S | FLAG_OV | FLAG_CY;
+const SHIFT_FLAGS: u32 = FLAG_Z | FLAG_S | FLAG_CY;
+const LOGIC_FLAGS: u32 = FLAG_Z | FLAG_S;
+const MUL_FLAGS: u32 = FLAG_Z | FLAG_S | FLAG_OV;
+
+// Everything the generators can emit. Bcond's condition lives in the op field, so it gets its own entry (with op
+//  holding only the top 3 bits).
+pub const OPCODES: &'static [Opcode] = &[
+    Opcode { name: "mov", op: 0b000000, subop: None, format: Format::I, imm: Imm::None, flags: 0 },
+    Opcode { name: "add", op: 0b000001, subop: None, format: Format::I, imm: Imm::None, flags: ARITH_FLAGS },
+    Opcode { name: "sub", op: 0b000010, subop: None, format: Format::I, imm: Imm::None, flags: ARITH_FLAGS },
+    Opcode { name: "cmp", op: 0b000011, subop: None, format: Format::I, imm: Imm::None, flags: ARITH_FLAGS },
+    Opcode { name: "shl", op: 0b000100, subop: None, format: Format::I, imm: Imm::None, flags: SHIFT_FLAGS },
+    Opcode { name: "shr", op: 0b000101, subop: None, format: Format::I, imm: Imm::None, flags: SHIFT_FLAGS },
+    Opcode { name: "jmp", op: 0b000110, subop: None, format: Format::I, imm: Imm::None, flags: 0 },
+    Opcode { name: "sar", op: 0b000111, subop: None, format: Format::I, imm: Imm::None, flags: SHIFT_FLAGS },
+    Opcode { name: "mul", op: 0b001000, subop: None, format: Format::I, imm: Imm::None, flags: MUL_FLAGS },
+    Opcode { name: "div", op: 0b001001, subop: None, format: Format::I, imm: Imm::None, flags: MUL_FLAGS },
+    Opcode { name: "mulu", op: 0b001010, subop: None, format: Format::I, imm: Imm::None, flags: MUL_FLAGS },
+    Opcode { name: "divu", op: 0b001011, subop: None, format: Format::I, imm: Imm::None, flags: LOGIC_FLAGS },
+    Opcode { name: "or", op: 0b001100, subop: None, format: Format::I, imm: Imm::None, flags: LOGIC_FLAGS },
+    Opcode { name: "and", op: 0b001101, subop: None, format: Format::I, imm: Imm::None, flags: LOGIC_FLAGS },
+    Opcode { name: "xor", op: 0b001110, subop: None, format: Format::I, imm: Imm::None, flags: LOGIC_FLAGS },
+    Opcode { name: "not", op: 0b001111, subop: None, format: Format::I, imm: Imm::None, flags: LOGIC_FLAGS },
+    Opcode { name: "mov imm", op: 0b010000, subop: None, format: Format::II, imm: Imm::Signed, flags: 0 },
+    Opcode { name: "add imm", op: 0b010001, subop: None, format: Format::II, imm: Imm::Signed, flags: ARITH_FLAGS },
+    Opcode { name: "setf", op: 0b010010, subop: None, format: Format::II, imm: Imm::None, flags: 0 },
+    Opcode { name: "cmp imm", op: 0b010011, subop: None, format: Format::II, imm: Imm::Signed, flags: ARITH_FLAGS },
+    Opcode { name: "shl imm", op: 0b010100, subop: None, format: Format::II, imm: Imm::Unsigned, flags: SHIFT_FLAGS },
+    Opcode { name: "shr imm", op: 0b010101, subop: None, format: Format::II, imm: Imm::Unsigned, flags: SHIFT_FLAGS },
+    Opcode { name: "sar imm", op: 0b010111, subop: None, format: Format::II, imm: Imm::Unsigned, flags: SHIFT_FLAGS },
+    Opcode { name: "trap", op: 0b011000, subop: None, format: Format::II, imm: Imm::None, flags: 0 },
+    Opcode { name: "ldsr", op: 0b011100, subop: None, format: Format::II, imm: Imm::None, flags: 0 },
+    Opcode { name: "stsr", op: 0b011101, subop: None, format: Format::II, imm: Imm::None, flags: 0 },
+    Opcode { name: "bcond", op: 0b100, subop: None, format: Format::III, imm: Imm::None, flags: 0 },
+    Opcode { name: "movea", op: 0b101000, subop: None, format: Format::V, imm: Imm::Signed, flags: 0 },
+    Opcode { name: "addi", op: 0b101001, subop: None, format: Format::V, imm: Imm::Signed, flags: ARITH_FLAGS },
+    Opcode { name: "jr", op: 0b101010, subop: None, format: Format::IV, imm: Imm::None, flags: 0 },
+    Opcode { name: "jal", op: 0b101011, subop: None, format: Format::IV, imm: Imm::None, flags: 0 },
+    Opcode { name: "ori", op: 0b101100, subop: None, format: Format::V, imm: Imm::Unsigned, flags: LOGIC_FLAGS },
+    Opcode { name: "andi", op: 0b101101, subop: None, format: Format::V, imm: Imm::Unsigned, flags: LOGIC_FLAGS },
+    Opcode { name: "xori", op: 0b101110, subop: None, format: Format::V, imm: Imm::Unsigned, flags: LOGIC_FLAGS },
+    Opcode { name: "movhi", op: 0b101111, subop: None, format: Format::V, imm: Imm::Unsigned, flags: 0 },
+    Opcode { name: "ld.b", op: 0b110000, subop: None, format: Format::VI, imm: Imm::Signed, flags: 0 },
+    Opcode { name: "ld.h", op: 0b110001, subop: None, format: Format::VI, imm: Imm::Signed, flags: 0 },
+    Opcode { name: "ld.w", op: 0b110011, subop: None, format: Format::VI, imm: Imm::Signed, flags: 0 },
+    Opcode { name: "st.b", op: 0b110100, subop: None, format: Format::VI, imm: Imm::Signed, flags: 0 },
+    Opcode { name: "st.h", op: 0b110101, subop: None, format: Format::VI, imm: Imm::Signed, flags: 0 },
+    Opcode { name: "st.w", op: 0b110111, subop: None, format: Format::VI, imm: Imm::Signed, flags: 0 },
+    Opcode { name: "xb", op: 0b111110, subop: Some(0b001000), format: Format::VII, imm: Imm::None, flags: 0 },
+    Opcode { name: "xh", op: 0b111110, subop: Some(0b001001), format: Format::VII, imm: Imm::None, flags: 0 },
+    Opcode { name: "rev", op: 0b111110, subop: Some(0b001010), format: Format::VII, imm: Imm::None, flags: 0 },
+    Opcode { name: "mpyhw", op: 0b111110, subop: Some(0b001100), format: Format::VII, imm: Imm::None, flags: 0 },
+];
+
+pub const PSW_REG_ID: u32 = 5;
+pub const CHCW_REG_ID: u32 = 24;
+
+pub const COND_NAMES: [&'static str; 16] = ["v", "c", "z", "nh", "n", "t", "lt", "le", "nv", "nc", "nz", "h", "p", "f", "ge", "gt"];
+
+pub struct Instruction {
+    // Byte offset from the start of the rom
+    pub offset: usize,
+    // None for anything the generators don't emit
+    pub opcode: Option<&'static Opcode>,
+    pub reg1: u32,
+    pub reg2: u32,
+    // imm5/imm16 (sign-extended for signed ops), condition for bcond/setf, regID for ldsr/stsr, vector for trap, disp16
+    //  for loads/stores
+    pub imm: i32,
+    // Branch displacement for bcond/jr
+    pub disp: i32,
+}
+
+impl Instruction {
+    pub fn name(&self) -> &'static str {
+        self.opcode.map(|opcode| opcode.name).unwrap_or("???")
+    }
+
+    pub fn disassemble(&self) -> String {
+        let opcode = match self.opcode {
+            Some(opcode) => opcode,
+            _ => return String::from("???"),
+        };
+        // "mov imm" and friends are just mov etc. in assembly
+        let mnemonic = opcode.name.split(' ').next().unwrap();
+
+        match (opcode.format, opcode.name) {
+            (Format::I, "jmp") => format!("jmp [r{}]", self.reg1),
+            (Format::I, _) | (Format::VII, _) => format!("{} r{}, r{}", mnemonic, self.reg1, self.reg2),
+            (Format::II, "setf") => format!("setf {}, r{}", COND_NAMES[self.imm as usize], self.reg2),
+            (Format::II, "stsr") => format!("stsr {}, r{}", system_reg_name(self.imm as u32), self.reg2),
+            (Format::II, "ldsr") => format!("ldsr r{}, {}", self.reg2, system_reg_name(self.imm as u32)),
+            (Format::II, "trap") => format!("trap {}", self.imm),
+            (Format::II, _) => format!("{} {}, r{}", mnemonic, self.imm, self.reg2),
+            (Format::III, _) => format!("b{} {:+}", COND_NAMES[self.imm as usize], self.disp),
+            (Format::IV, _) => format!("{} {:+}", opcode.name, self.disp),
+            (Format::V, _) if opcode.imm == Imm::Signed => format!("{} {}, r{}, r{}", mnemonic, self.imm, self.reg1, self.reg2),
+            (Format::V, _) => format!("{} 0x{:04x}, r{}, r{}", mnemonic, self.imm, self.reg1, self.reg2),
+            (Format::VI, name) if is_store(name) => format!("{} r{}, {}[r{}]", mnemonic, self.reg2, self.imm, self.reg1),
+            (Format::VI, _) => format!("{} {}[r{}], r{}", mnemonic, self.imm, self.reg1, self.reg2),
+        }
+    }
+
+    pub fn is_branch(&self) -> bool {
+        match self.opcode {
+            Some(opcode) => opcode.format == Format::III || opcode.format == Format::IV || opcode.name == "jmp",
+            _ => false,
+        }
+    }
+
+    // Regs this instruction writes, not counting r0
+    pub fn dest_regs(&self) -> Vec<u32> {
+        let opcode = match self.opcode {
+            Some(opcode) => opcode,
+            _ => return Vec::new(),
+        };
+
+        let mut ret = match (opcode.format, opcode.name) {
+            (Format::I, "cmp") | (Format::II, "cmp imm") | (Format::I, "jmp") | (Format::II, "trap") | (Format::II, "ldsr") | (Format::III, _) => Vec::new(),
+            (Format::IV, "jal") => vec![31],
+            (Format::IV, _) => Vec::new(),
+            (Format::VI, name) if is_store(name) => Vec::new(),
+            _ => vec![self.reg2],
+        };
+        match opcode.name {
+            "mul" | "mulu" | "div" | "divu" => ret.push(30),
+            _ => (),
+        }
+        ret.retain(|&reg| reg != 0);
+        ret
+    }
+}
+
+fn system_reg_name(id: u32) -> String {
+    match id {
+        PSW_REG_ID => String::from("psw"),
+        CHCW_REG_ID => String::from("chcw"),
+        _ => id.to_string(),
+    }
+}
+
+fn is_store(name: &str) -> bool {
+    name.starts_with("st.")
+}
+
+// One line per instruction, with its byte offset into the rom
+pub fn listing(rom: &[u8]) -> String {
+    decode(rom).iter().map(|instruction| format!("0x{:04x}: {}\n", instruction.offset, instruction.disassemble())).collect()
+}
+
+pub fn decode(rom: &[u8]) -> Vec<Instruction> {
+    let mut ret = Vec::new();
+
+    let mut offset = 0;
+    while offset + 2 <= rom.len() {
+        let first_halfword = LittleEndian::read_u16(&rom[offset..offset + 2]);
+        let op = first_halfword >> 10;
+        let len = if op >= 0b101000 { 4 } else { 2 };
+        if offset + len > rom.len() {
+            break;
+        }
+        let second_halfword = if len == 4 { LittleEndian::read_u16(&rom[offset + 2..offset + 4]) } else { 0 };
+
+        let opcode = OPCODES.iter().find(|opcode| match opcode.format {
+            Format::III => op >> 3 == opcode.op,
+            Format::VII => op == opcode.op && Some(second_halfword >> 10) == opcode.subop,
+            _ => op == opcode.op,
+        });
+
+        let reg1 = (first_halfword & 0x1f) as u32;
+        let reg2 = ((first_halfword >> 5) & 0x1f) as u32;
+        let imm = match opcode.map(|opcode| (opcode.format, opcode.imm)) {
+            Some((Format::II, Imm::Signed)) => ((reg1 << 27) as i32) >> 27,
+            // setf only looks at the low 4 bits of its condition
+            _ if opcode.map(|opcode| opcode.name) == Some("setf") => (reg1 & 0x0f) as i32,
+            Some((Format::II, _)) => reg1 as i32,
+            Some((Format::III, _)) => ((first_halfword >> 9) & 0x0f) as i32,
+            Some((Format::V, Imm::Signed)) => second_halfword as i16 as i32,
+            Some((Format::V, _)) => second_halfword as i32,
+            Some((Format::VI, _)) => second_halfword as i16 as i32,
+            _ => 0,
+        };
+        let disp = match opcode.map(|opcode| opcode.format) {
+            Some(Format::III) => (((first_halfword as u32) << 23) as i32) >> 23,
+            Some(Format::IV) => (((((first_halfword as u32) << 16) | (second_halfword as u32)) << 6) as i32) >> 6,
+            _ => 0,
+        };
+
+        ret.push(Instruction {
+            offset: offset,
+            opcode: opcode,
+            reg1: reg1,
+            reg2: reg2,
+            imm: imm,
+            disp: disp,
+        });
+
+        offset += len;
+    }
+
+    ret
+}
@@ -7,44 +7,73 @@ use rustual_boy_middleware::{Anaglyphizer, GammaAdjustSink, MostRecentSink};
 
 use minifb::{WindowOptions, Window, Scale};
 
+use target::Link;
+use trace::TraceEntry;
+
 use std::collections::VecDeque;
 use std::io::{self, Read, Write};
 
 pub struct EmulatedVbSerialPort {
-    window: Window,
+    window: Option<Window>,
 
     virtual_boy: VirtualBoy,
     emulated_time_ns: u64,
 
     response_buffer: VecDeque<u8>,
+
+    // Instructions executed between these addresses get traced while this is set
+    trace_range: Option<(u32, u32)>,
+    trace: Vec<TraceEntry>,
 }
 
+// A program that never returns would trace until the harness gives up on it
+const MAX_TRACE_ENTRIES: usize = 100_000;
+
+// Enough emulated time for the loader to boot and start listening
+const BOOT_TIME_NS: u64 = 1_000_000_000;
+
 impl EmulatedVbSerialPort {
     pub fn new() -> EmulatedVbSerialPort {
-        let rom = Rom::load("../loader/build/loader.vb").expect("Couldn't load loader ROM for emulated VB");
-        let sram = Sram::new();
-        let virtual_boy = VirtualBoy::new(rom, sram);
+        let window = Window::new("Rustual Boy", 384, 224, WindowOptions {
+            borderless: false,
+            title: true,
+            resize: false,
+            scale: Scale::X2,
+        }).unwrap();
+        EmulatedVbSerialPort::with_window(Some(window))
+    }
+
+    // Extra instances go without, so they don't clutter the screen
+    pub fn without_window() -> EmulatedVbSerialPort {
+        EmulatedVbSerialPort::with_window(None)
+    }
 
+    fn with_window(window: Option<Window>) -> EmulatedVbSerialPort {
         let mut ret = EmulatedVbSerialPort {
-            window: Window::new("Rustual Boy", 384, 224, WindowOptions {
-                borderless: false,
-                title: true,
-                resize: false,
-                scale: Scale::X2,
-            }).unwrap(),
-
-            virtual_boy: virtual_boy,
+            window: window,
+
+            virtual_boy: boot_virtual_boy(),
             emulated_time_ns: 0,
 
             response_buffer: VecDeque::new(),
+
+            trace_range: None,
+            trace: Vec::new(),
         };
 
-        // Step VB 1s emulated time to let it boot into the test ROM before use
-        ret.step_ns(1_000_000_000);
+        ret.step_ns(BOOT_TIME_NS);
 
         ret
     }
 
+    // Puts the VB back in the state it boots into, so nothing a test does can leak into the next one. VirtualBoy has no
+    //  way to copy its state, so this boots a fresh one.
+    pub fn reboot(&mut self) {
+        self.virtual_boy = boot_virtual_boy();
+        self.response_buffer.clear();
+        self.step_ns(BOOT_TIME_NS);
+    }
+
     fn step_ns(&mut self, ns: u64) {
         const CPU_CYCLE_TIME_NS: u64 = 50;
 
@@ -58,16 +87,35 @@ impl EmulatedVbSerialPort {
 
         let target_emulated_time_ns = self.emulated_time_ns + ns;
         while self.emulated_time_ns < target_emulated_time_ns {
-            let (emulated_cycles, _) = self.virtual_boy.step(&mut video_frame_sink, &mut NullAudioFrameSink);
-            self.emulated_time_ns += (emulated_cycles as u64) * CPU_CYCLE_TIME_NS;
+            let pc = self.virtual_boy.cpu.reg_pc();
+            let traced = match self.trace_range {
+                Some((start, end)) => pc >= start && pc < end && self.trace.len() < MAX_TRACE_ENTRIES,
+                _ => false,
+            };
+
+            if traced {
+                let regs_before = self.regs();
+                let psw_before = self.virtual_boy.cpu.reg_psw();
+                let (emulated_cycles, _) = self.virtual_boy.step(&mut video_frame_sink, &mut NullAudioFrameSink);
+                self.emulated_time_ns += (emulated_cycles as u64) * CPU_CYCLE_TIME_NS;
+                let entry = TraceEntry::new(pc, &regs_before, &self.regs(), psw_before, self.virtual_boy.cpu.reg_psw());
+                self.trace.push(entry);
+            } else {
+                let (emulated_cycles, _) = self.virtual_boy.step(&mut video_frame_sink, &mut NullAudioFrameSink);
+                self.emulated_time_ns += (emulated_cycles as u64) * CPU_CYCLE_TIME_NS;
+            }
         }
 
-        if let Some(frame) = video_frame_sink.into_inner().into_inner().into_inner() {
+        if let (Some(frame), Some(ref mut window)) = (video_frame_sink.into_inner().into_inner().into_inner(), self.window.as_mut()) {
             let frame: Vec<u32> = frame.into_iter().map(|x| x.into()).collect();
-            self.window.update_with_buffer(&frame);
+            window.update_with_buffer(&frame);
         }
     }
 
+    fn regs(&self) -> Vec<u32> {
+        (0..32).map(|reg| self.virtual_boy.cpu.reg_gpr(reg)).collect()
+    }
+
     fn transfer_byte(&mut self, send_byte: u8) -> u8 {
         let mut received_byte = 0;
 
@@ -126,6 +174,9 @@ impl Write for EmulatedVbSerialPort {
 
                 handshake_tries += 1;
                 if handshake_tries >= MAX_HANDSHAKE_TRIES {
+                    // A real host would sit on its read timeout here, so let the VB keep running for a while too
+                    self.step_ns(10_000_000);
+
                     return Err(io::Error::new(io::ErrorKind::Other, "Emulated VB didn't respond to send handshake"));
                 }
             }
@@ -155,6 +206,9 @@ impl Write for EmulatedVbSerialPort {
 
                 handshake_tries += 1;
                 if handshake_tries >= MAX_HANDSHAKE_TRIES {
+                    // A real host would sit on its read timeout here, so let the VB keep running for a while too
+                    self.step_ns(10_000_000);
+
                     return Err(io::Error::new(io::ErrorKind::Other, "Emulated VB didn't respond to receive handshake"));
                 }
             }
@@ -183,6 +237,34 @@ impl Write for EmulatedVbSerialPort {
     }
 }
 
+fn boot_virtual_boy() -> VirtualBoy {
+    let rom = Rom::load("../loader/build/loader.vb").expect("Couldn't load loader ROM for emulated VB");
+    let sram = Sram::new();
+    VirtualBoy::new(rom, sram)
+}
+
+impl Link for EmulatedVbSerialPort {
+    fn recover(&mut self) -> Result<(), String> {
+        // The emulated link can't get wedged the way the real one can
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.reboot();
+    }
+
+    fn start_trace(&mut self, start: u32, end: u32) -> bool {
+        self.trace_range = Some((start, end));
+        self.trace.clear();
+        true
+    }
+
+    fn take_trace(&mut self) -> Vec<TraceEntry> {
+        self.trace_range = None;
+        self.trace.split_off(0)
+    }
+}
+
 /*struct NullVideoFrameSink;
 
 impl Sink<VideoFrame> for NullVideoFrameSink {
@@ -0,0 +1,1288 @@
+use rand::{Rng, StdRng, SeedableRng};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use values::ValueStrategy;
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+pub trait Generator {
+    fn next(&mut self, buf: &mut Vec<u8>);
+}
+
+pub struct MultiGenerator {
+    generators: Vec<(String, u32, Box<Generator>)>,
+    rng: StdRng,
+
+    // How many times each generator has been picked
+    picks: Vec<u64>,
+}
+
+impl MultiGenerator {
+    pub fn new(generators: Vec<Box<Generator>>, rng: StdRng) -> MultiGenerator {
+        // Unnamed generators can still be weighted by their index
+        MultiGenerator::named(generators.into_iter().enumerate().map(|(index, generator)| (index.to_string(), generator)).collect(), rng)
+    }
+
+    pub fn named(generators: Vec<(String, Box<Generator>)>, rng: StdRng) -> MultiGenerator {
+        let num_generators = generators.len();
+        MultiGenerator {
+            generators: generators.into_iter().map(|(name, generator)| (name, 1, generator)).collect(),
+            rng: rng,
+
+            picks: vec![0; num_generators],
+        }
+    }
+
+    pub fn picks(&self) -> Vec<(&str, u64)> {
+        self.generators.iter().zip(self.picks.iter()).map(|(&(ref name, _, _), &picks)| (name.as_str(), picks)).collect()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.generators.iter().map(|&(ref name, _, _)| name.as_str()).collect()
+    }
+
+    pub fn weight(&self, name: &str) -> Option<u32> {
+        self.generators.iter().find(|&&(ref generator_name, _, _)| generator_name == name).map(|&(_, weight, _)| weight)
+    }
+
+    // Keeps the old weight if the new one would leave nothing to pick (see total_weight)
+    pub fn set_weight(&mut self, name: &str, weight: u32) -> Result<(), String> {
+        let index = self.index(name)?;
+        let old_weight = self.generators[index].1;
+        self.generators[index].1 = weight;
+        if let Err(e) = self.total_weight() {
+            self.generators[index].1 = old_weight;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub fn apply_mix(&mut self, mix: &Mix) -> Result<(), String> {
+        for &mut (_, ref mut weight, _) in self.generators.iter_mut() {
+            *weight = mix.default_weight;
+        }
+        // Only the end result has to be pickable, so the weights go in directly
+        for &(ref name, weight) in mix.weights.iter() {
+            let index = self.index(name)?;
+            self.generators[index].1 = weight;
+        }
+
+        self.total_weight().map(|_| ()).map_err(|e| format!("Invalid mix: {}", e))
+    }
+
+    fn index(&self, name: &str) -> Result<usize, String> {
+        self.generators.iter().position(|&(ref generator_name, _, _)| generator_name == name).ok_or_else(|| format!("Unknown generator `{}`", name))
+    }
+
+    // Picking draws a u32, so the weights have to add up to something in 1..=u32::MAX
+    fn total_weight(&self) -> Result<u32, String> {
+        let mut total: u32 = 0;
+        for &(_, weight, _) in self.generators.iter() {
+            total = total.checked_add(weight).ok_or_else(|| format!("weights add up to more than {}", u32::max_value()))?;
+        }
+
+        if total == 0 {
+            return Err("every generator has zero weight".into());
+        }
+
+        Ok(total)
+    }
+}
+
+impl Generator for MultiGenerator {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let mut choice = self.rng.gen::<u32>() % self.total_weight().expect("Weights are checked whenever they change");
+        for (index, &mut (_, weight, ref mut generator)) in self.generators.iter_mut().enumerate() {
+            if choice < weight {
+                generator.next(buf);
+                self.picks[index] += 1;
+                return;
+            }
+            choice -= weight;
+        }
+    }
+}
+
+// Relative weights for the generators in build_all_generator. Generators that aren't mentioned get default_weight.
+#[derive(Clone, Debug)]
+pub struct Mix {
+    default_weight: u32,
+    weights: Vec<(String, u32)>,
+}
+
+pub const MIX_PRESETS: &'static [&'static str] = &["uniform", "alu-heavy", "flags-heavy", "divide-heavy"];
+
+impl Mix {
+    pub fn uniform() -> Mix {
+        Mix {
+            default_weight: 1,
+            weights: Vec::new(),
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Mix> {
+        let (default_weight, weights): (u32, &[(&str, u32)]) = match name {
+            "uniform" => (1, &[]),
+            // Plain ALU ops dominate; the slow/special ones still show up now and then
+            "alu-heavy" => (4, &[
+                ("mul", 1),
+                ("mulu", 1),
+                ("mpyhw", 1),
+                ("div", 1),
+                ("divu", 1),
+                ("stsr_psw", 1),
+                ("setf", 1),
+            ]),
+            // Lots of flag producers, with frequent psw reads so flag bugs don't get overwritten before they're seen
+            "flags-heavy" => (1, &[
+                ("stsr_psw", 8),
+                ("setf", 8),
+                ("cmp_reg", 6),
+                ("cmp_imm", 6),
+                ("add_reg", 3),
+                ("add_imm", 3),
+                ("addi", 3),
+                ("sub", 3),
+            ]),
+            "divide-heavy" => (1, &[
+                ("div", 12),
+                ("divu", 12),
+                ("stsr_psw", 4),
+            ]),
+            _ => return None,
+        };
+
+        Some(Mix {
+            default_weight: default_weight,
+            weights: weights.iter().map(|&(name, weight)| (name.into(), weight)).collect(),
+        })
+    }
+
+    // Mix files have one `<generator> <weight>` pair per line. `default <weight>` sets the weight for generators that
+    //  aren't listed, `preset <name>` starts from a preset, and anything after a `#` is ignored.
+    pub fn parse(text: &str) -> Result<Mix, String> {
+        let mut mix = Mix::uniform();
+
+        for (line_index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() != 2 {
+                return Err(format!("Line {}: expected `<name> <value>`", line_index + 1));
+            }
+
+            match fields[0] {
+                "preset" => {
+                    mix = Mix::preset(fields[1]).ok_or_else(|| format!("Line {}: unknown preset `{}`", line_index + 1, fields[1]))?;
+                }
+                name => {
+                    let weight = fields[1].parse::<u32>().map_err(|e| format!("Line {}: invalid weight: {}", line_index + 1, e))?;
+                    if name == "default" {
+                        mix.default_weight = weight;
+                    } else {
+                        mix.set(name, weight);
+                    }
+                }
+            }
+        }
+
+        mix.validate()?;
+
+        Ok(mix)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Mix, String> {
+        let mut text = String::new();
+        File::open(path).and_then(|mut file| file.read_to_string(&mut text)).map_err(|e| format!("Couldn't read mix file: {}", e))?;
+        Mix::parse(&text)
+    }
+
+    pub fn set(&mut self, name: &str, weight: u32) {
+        self.weights.retain(|&(ref weight_name, _)| weight_name != name);
+        self.weights.push((name.into(), weight));
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        // Make sure every name refers to an actual generator and that something can still be picked
+        build_all_generator(&mut build_rng(0), &Mix::uniform(), ValueStrategy::Uniform).apply_mix(self)
+    }
+}
+
+pub struct AlternatingGenerator {
+    generators: [Box<Generator>; 2],
+    index: usize,
+}
+
+impl AlternatingGenerator {
+    pub fn new(a: Box<Generator>, b: Box<Generator>) -> AlternatingGenerator {
+        AlternatingGenerator {
+            generators: [a, b],
+            index: 0,
+        }
+    }
+}
+
+impl Generator for AlternatingGenerator {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        self.generators[self.index].next(buf);
+        self.index = 1 - self.index;
+    }
+}
+
+pub struct BranchingGenerator {
+    block_instruction_generator: Box<Generator>,
+    rng: StdRng,
+}
+
+impl BranchingGenerator {
+    pub fn new(block_instruction_generator: Box<Generator>, rng: StdRng) -> BranchingGenerator {
+        BranchingGenerator {
+            block_instruction_generator: block_instruction_generator,
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for BranchingGenerator {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        /*
+
+            block0 {
+                ...
+                bcond block1
+                jr block2
+            }
+
+            block1/2 {
+                ...
+                jr exit
+            }
+
+enter:
+            jr block0
+slot0:
+            [one of block0/1/2]
+slot1:
+            [one of block0/1/2]
+slot2:
+            [one of block0/1/2]
+exit:
+
+        */
+
+        // Generate blocks
+        let mut blocks = Vec::new();
+        for i in 0..3 {
+            let mut instructions = Vec::new();
+            let num_instrs = self.rng.gen::<u32>() % 3 + 1;
+            for _ in 0..num_instrs {
+                self.block_instruction_generator.next(&mut instructions);
+            }
+            let branches = if i == 0 {
+                vec![Branch::random_bcond(&mut self.rng), Branch::Jr { addr: None, target: None }]
+            } else {
+                vec![Branch::Jr { addr: None, target: None }]
+            };
+            blocks.push(Block::new(instructions, branches));
+        }
+
+        // Assign blocks to available slots
+        let mut slot_block_indices = [0, 1, 2];
+        self.rng.shuffle(&mut slot_block_indices);
+        let mut block_slot_indices = [0; 3];
+        for i in 0..3 {
+            block_slot_indices[slot_block_indices[i]] = i;
+        }
+
+        // Flatten blocks in their respective slots. Branches only encode displacements, so laying blocks out by their
+        //  offset into the rom works wherever it ends up loaded.
+        let enter = buf.len() as u32;
+        let mut enter_branch = Branch::Jr { addr: Some(enter), target: None };
+        let slot0 = enter + (enter_branch.len() as u32);
+        let slot1 = slot0 + (blocks[slot_block_indices[0]].len() as u32);
+        let slot2 = slot1 + (blocks[slot_block_indices[1]].len() as u32);
+        let exit = slot2 + (blocks[slot_block_indices[2]].len() as u32);
+        blocks[slot_block_indices[0]].flatten(slot0);
+        blocks[slot_block_indices[1]].flatten(slot1);
+        blocks[slot_block_indices[2]].flatten(slot2);
+
+        // Resolve branch addr's
+        enter_branch.set_target(blocks[0].addr.unwrap());
+        for i in 0..3 {
+            if i == 0 {
+                let branch_target = blocks[1].addr.unwrap();
+                blocks[i].branches[0].set_target(branch_target);
+                let jump_target = blocks[2].addr.unwrap();
+                blocks[i].branches[1].set_target(jump_target);
+            } else {
+                blocks[i].branches[0].set_target(exit);
+            }
+        }
+
+        // Serialize
+        enter_branch.serialize(buf);
+        //  Make sure blocks are serialized in slot order
+        for i in 0..3 {
+            blocks[slot_block_indices[i]].serialize(buf);
+        }
+    }
+}
+
+// TODO: Also support jmp, which requires setting a reg value first
+#[derive(Debug)]
+enum Branch {
+    BCond { addr: Option<u32>, target: Option<u32>, cond: u32 },
+    Jr { addr: Option<u32>, target: Option<u32> },
+}
+
+impl Branch {
+    fn random_bcond(rng: &mut StdRng) -> Branch {
+        Branch::BCond { addr: None, target: None, cond: rng.gen::<u32>() & 0x0f }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            &Branch::BCond { .. } => 2,
+            &Branch::Jr { .. } => 4,
+        }
+    }
+
+    fn set_addr(&mut self, value: u32) {
+        let value = Some(value);
+        match self {
+            &mut Branch::BCond { ref mut addr, .. } => *addr = value,
+            &mut Branch::Jr { ref mut addr, .. } => *addr = value,
+        }
+    }
+
+    fn set_target(&mut self, value: u32) {
+        let value = Some(value);
+        match self {
+            &mut Branch::BCond { ref mut target, .. } => *target = value,
+            &mut Branch::Jr { ref mut target, .. } => *target = value,
+        }
+    }
+
+    fn serialize(&self, buf: &mut Vec<u8>) {
+        match self {
+            &Branch::BCond { addr, target, cond } => {
+                let op = (0b100 << 4) | cond;
+                let disp9 = target.unwrap().wrapping_sub(addr.unwrap()) & 0b11111111_1;
+                buf.write_u16::<LittleEndian>(((op << 9) | disp9) as u16).unwrap();
+            }
+            &Branch::Jr { addr, target } => {
+                let op = 0b101010;
+                let disp26 = target.unwrap().wrapping_sub(addr.unwrap()) & 0b11111111_11111111_11111111_11;
+                buf.write_u16::<LittleEndian>(((op << 10) | (disp26 >> 16)) as u16).unwrap();
+                buf.write_u16::<LittleEndian>(disp26 as u16).unwrap();
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Block {
+    addr: Option<u32>,
+    instructions: Vec<u8>,
+    branches: Vec<Branch>,
+}
+
+impl Block {
+    fn new(instructions: Vec<u8>, branches: Vec<Branch>) -> Block {
+        Block {
+            addr: None,
+            instructions: instructions,
+            branches: branches,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.instructions.len() + self.branches.iter().map(|x| x.len()).sum::<usize>()
+    }
+
+    fn flatten(&mut self, mut addr: u32) {
+        self.addr = Some(addr);
+        addr += self.instructions.len() as u32;
+        for branch in self.branches.iter_mut() {
+            branch.set_addr(addr);
+            addr += branch.len() as u32;
+        }
+    }
+
+    fn serialize(&self, buf: &mut Vec<u8>) {
+        buf.extend(&self.instructions);
+        for branch in self.branches.iter() {
+            branch.serialize(buf);
+        }
+    }
+}
+
+// Ends a test. The harness takes trap 0 as the way back, so tests don't need a link reg and r31 is free to use.
+pub struct Ret;
+
+impl Generator for Ret {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b011000;
+        let vector = 0;
+        buf.write_u16::<LittleEndian>((op << 10) | vector).unwrap();
+    }
+}
+
+// jal to the very next instruction, so the only effect is the return addr it leaves in r31
+pub struct JalNext;
+
+impl Generator for JalNext {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b101011;
+        let disp26: u32 = 4;
+        buf.write_u16::<LittleEndian>(((op << 10) | (disp26 >> 16)) as u16).unwrap();
+        buf.write_u16::<LittleEndian>(disp26 as u16).unwrap();
+    }
+}
+
+pub struct Mul {
+    rng: StdRng,
+}
+
+impl Mul {
+    pub fn new(rng: StdRng) -> Mul {
+        Mul {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for Mul {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b001000;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+    }
+}
+
+pub struct StsrPsw {
+    rng: StdRng,
+}
+
+impl StsrPsw {
+    pub fn new(rng: StdRng) -> StsrPsw {
+        StsrPsw {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for StsrPsw {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b011101;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        let imm5 = 5;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | imm5).unwrap();
+    }
+}
+
+pub struct Movea {
+    rng: StdRng,
+    values: ValueStrategy,
+}
+
+impl Movea {
+    pub fn new(rng: StdRng, values: ValueStrategy) -> Movea {
+        Movea {
+            rng: rng,
+            values: values,
+        }
+    }
+}
+
+impl Generator for Movea {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b101000;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        let imm16 = self.values.gen_bits(&mut self.rng, 16) as i16;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+        buf.write_u16::<LittleEndian>(imm16 as u16).unwrap();
+    }
+}
+
+pub struct Movhi {
+    rng: StdRng,
+    values: ValueStrategy,
+}
+
+impl Movhi {
+    pub fn new(rng: StdRng, values: ValueStrategy) -> Movhi {
+        Movhi {
+            rng: rng,
+            values: values,
+        }
+    }
+}
+
+impl Generator for Movhi {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b101111;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        let imm16 = self.values.gen_bits(&mut self.rng, 16) as i16;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+        buf.write_u16::<LittleEndian>(imm16 as u16).unwrap();
+    }
+}
+
+pub struct MovReg {
+    rng: StdRng,
+}
+
+impl MovReg {
+    pub fn new(rng: StdRng) -> MovReg {
+        MovReg {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for MovReg {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b000000;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+    }
+}
+
+pub struct MovImm {
+    rng: StdRng,
+    values: ValueStrategy,
+}
+
+impl MovImm {
+    pub fn new(rng: StdRng, values: ValueStrategy) -> MovImm {
+        MovImm {
+            rng: rng,
+            values: values,
+        }
+    }
+}
+
+impl Generator for MovImm {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b010000;
+        let imm5 = self.values.gen_bits(&mut self.rng, 5);
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (imm5 as u16)).unwrap();
+    }
+}
+
+pub struct Mulu {
+    rng: StdRng,
+}
+
+impl Mulu {
+    pub fn new(rng: StdRng) -> Mulu {
+        Mulu {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for Mulu {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b001010;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+    }
+}
+
+pub struct Not {
+    rng: StdRng,
+}
+
+impl Not {
+    pub fn new(rng: StdRng) -> Not {
+        Not {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for Not {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b001111;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+    }
+}
+
+pub struct Or {
+    rng: StdRng,
+}
+
+impl Or {
+    pub fn new(rng: StdRng) -> Or {
+        Or {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for Or {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b001100;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+    }
+}
+
+pub struct Ori {
+    rng: StdRng,
+    values: ValueStrategy,
+}
+
+impl Ori {
+    pub fn new(rng: StdRng, values: ValueStrategy) -> Ori {
+        Ori {
+            rng: rng,
+            values: values,
+        }
+    }
+}
+
+impl Generator for Ori {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b101100;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        let imm16 = self.values.gen_bits(&mut self.rng, 16) as u16;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+        buf.write_u16::<LittleEndian>(imm16).unwrap();
+    }
+}
+
+pub struct SarReg {
+    rng: StdRng,
+}
+
+impl SarReg {
+    pub fn new(rng: StdRng) -> SarReg {
+        SarReg {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for SarReg {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b000111;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+    }
+}
+
+pub struct SarImm {
+    rng: StdRng,
+    values: ValueStrategy,
+}
+
+impl SarImm {
+    pub fn new(rng: StdRng, values: ValueStrategy) -> SarImm {
+        SarImm {
+            rng: rng,
+            values: values,
+        }
+    }
+}
+
+impl Generator for SarImm {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b010111;
+        let imm5 = self.values.gen_bits(&mut self.rng, 5);
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (imm5 as u16)).unwrap();
+    }
+}
+
+pub struct Setf {
+    rng: StdRng,
+}
+
+impl Setf {
+    pub fn new(rng: StdRng) -> Setf {
+        Setf {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for Setf {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b010010;
+        let imm5 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (imm5 as u16)).unwrap();
+    }
+}
+
+pub struct ShlReg {
+    rng: StdRng,
+}
+
+impl ShlReg {
+    pub fn new(rng: StdRng) -> ShlReg {
+        ShlReg {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for ShlReg {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b000100;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+    }
+}
+
+pub struct ShlImm {
+    rng: StdRng,
+    values: ValueStrategy,
+}
+
+impl ShlImm {
+    pub fn new(rng: StdRng, values: ValueStrategy) -> ShlImm {
+        ShlImm {
+            rng: rng,
+            values: values,
+        }
+    }
+}
+
+impl Generator for ShlImm {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b010100;
+        let imm5 = self.values.gen_bits(&mut self.rng, 5);
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (imm5 as u16)).unwrap();
+    }
+}
+
+pub struct ShrReg {
+    rng: StdRng,
+}
+
+impl ShrReg {
+    pub fn new(rng: StdRng) -> ShrReg {
+        ShrReg {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for ShrReg {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b000101;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+    }
+}
+
+pub struct ShrImm {
+    rng: StdRng,
+    values: ValueStrategy,
+}
+
+impl ShrImm {
+    pub fn new(rng: StdRng, values: ValueStrategy) -> ShrImm {
+        ShrImm {
+            rng: rng,
+            values: values,
+        }
+    }
+}
+
+impl Generator for ShrImm {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b010101;
+        let imm5 = self.values.gen_bits(&mut self.rng, 5);
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (imm5 as u16)).unwrap();
+    }
+}
+
+pub struct Sub {
+    rng: StdRng,
+}
+
+impl Sub {
+    pub fn new(rng: StdRng) -> Sub {
+        Sub {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for Sub {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b000010;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+    }
+}
+
+pub struct Xor {
+    rng: StdRng,
+}
+
+impl Xor {
+    pub fn new(rng: StdRng) -> Xor {
+        Xor {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for Xor {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b001110;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+    }
+}
+
+pub struct Xori {
+    rng: StdRng,
+    values: ValueStrategy,
+}
+
+impl Xori {
+    pub fn new(rng: StdRng, values: ValueStrategy) -> Xori {
+        Xori {
+            rng: rng,
+            values: values,
+        }
+    }
+}
+
+impl Generator for Xori {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b101110;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        let imm16 = self.values.gen_bits(&mut self.rng, 16) as u16;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+        buf.write_u16::<LittleEndian>(imm16).unwrap();
+    }
+}
+
+pub struct AddReg {
+    rng: StdRng,
+}
+
+impl AddReg {
+    pub fn new(rng: StdRng) -> AddReg {
+        AddReg {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for AddReg {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b000001;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+    }
+}
+
+pub struct AddImm {
+    rng: StdRng,
+    values: ValueStrategy,
+}
+
+impl AddImm {
+    pub fn new(rng: StdRng, values: ValueStrategy) -> AddImm {
+        AddImm {
+            rng: rng,
+            values: values,
+        }
+    }
+}
+
+impl Generator for AddImm {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b010001;
+        let imm5 = self.values.gen_bits(&mut self.rng, 5);
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (imm5 as u16)).unwrap();
+    }
+}
+
+pub struct AddI {
+    rng: StdRng,
+    values: ValueStrategy,
+}
+
+impl AddI {
+    pub fn new(rng: StdRng, values: ValueStrategy) -> AddI {
+        AddI {
+            rng: rng,
+            values: values,
+        }
+    }
+}
+
+impl Generator for AddI {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b101001;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        let imm16 = self.values.gen_bits(&mut self.rng, 16) as i16;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+        buf.write_u16::<LittleEndian>(imm16 as u16).unwrap();
+    }
+}
+
+pub struct And {
+    rng: StdRng,
+}
+
+impl And {
+    pub fn new(rng: StdRng) -> And {
+        And {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for And {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b001101;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+    }
+}
+
+pub struct AndI {
+    rng: StdRng,
+    values: ValueStrategy,
+}
+
+impl AndI {
+    pub fn new(rng: StdRng, values: ValueStrategy) -> AndI {
+        AndI {
+            rng: rng,
+            values: values,
+        }
+    }
+}
+
+impl Generator for AndI {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b101101;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        let imm16 = self.values.gen_bits(&mut self.rng, 16) as u16;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+        buf.write_u16::<LittleEndian>(imm16).unwrap();
+    }
+}
+
+pub struct CmpReg {
+    rng: StdRng,
+}
+
+impl CmpReg {
+    pub fn new(rng: StdRng) -> CmpReg {
+        CmpReg {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for CmpReg {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b000011;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+    }
+}
+
+pub struct CmpImm {
+    rng: StdRng,
+    values: ValueStrategy,
+}
+
+impl CmpImm {
+    pub fn new(rng: StdRng, values: ValueStrategy) -> CmpImm {
+        CmpImm {
+            rng: rng,
+            values: values,
+        }
+    }
+}
+
+impl Generator for CmpImm {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b010011;
+        let imm5 = self.values.gen_bits(&mut self.rng, 5);
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (imm5 as u16)).unwrap();
+    }
+}
+
+pub struct Mpyhw {
+    rng: StdRng,
+}
+
+impl Mpyhw {
+    pub fn new(rng: StdRng) -> Mpyhw {
+        Mpyhw {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for Mpyhw {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b111110;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        let subop = 0b001100;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+        buf.write_u16::<LittleEndian>(subop << 10).unwrap();
+    }
+}
+
+pub struct Rev {
+    rng: StdRng,
+}
+
+impl Rev {
+    pub fn new(rng: StdRng) -> Rev {
+        Rev {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for Rev {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b111110;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        let subop = 0b001010;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+        buf.write_u16::<LittleEndian>(subop << 10).unwrap();
+    }
+}
+
+pub struct Xb {
+    rng: StdRng,
+}
+
+impl Xb {
+    pub fn new(rng: StdRng) -> Xb {
+        Xb {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for Xb {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b111110;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        let subop = 0b001000;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+        buf.write_u16::<LittleEndian>(subop << 10).unwrap();
+    }
+}
+
+pub struct Xh {
+    rng: StdRng,
+}
+
+impl Xh {
+    pub fn new(rng: StdRng) -> Xh {
+        Xh {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for Xh {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b111110;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        let subop = 0b001001;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+        buf.write_u16::<LittleEndian>(subop << 10).unwrap();
+    }
+}
+
+pub struct Div {
+    rng: StdRng,
+}
+
+impl Div {
+    pub fn new(rng: StdRng) -> Div {
+        Div {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for Div {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b001001;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+    }
+}
+
+pub struct Divu {
+    rng: StdRng,
+}
+
+impl Divu {
+    pub fn new(rng: StdRng) -> Divu {
+        Divu {
+            rng: rng,
+        }
+    }
+}
+
+impl Generator for Divu {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        let op = 0b001011;
+        let reg1 = self.rng.gen::<u32>() % 32;
+        let reg2 = self.rng.gen::<u32>() % 32;
+        buf.write_u16::<LittleEndian>((op << 10) | ((reg2 as u16) << 5) | (reg1 as u16)).unwrap();
+    }
+}
+
+pub fn build_all_generator(rng: &mut StdRng, mix: &Mix, values: ValueStrategy) -> MultiGenerator {
+    let mul = Mul::new(build_rng(rng.gen::<usize>()));
+    let stsr_psw = StsrPsw::new(build_rng(rng.gen::<usize>()));
+    let movea = Movea::new(build_rng(rng.gen::<usize>()), values);
+    let movhi = Movhi::new(build_rng(rng.gen::<usize>()), values);
+    let mov_reg = MovReg::new(build_rng(rng.gen::<usize>()));
+    let mov_imm = MovImm::new(build_rng(rng.gen::<usize>()), values);
+    let mulu = Mulu::new(build_rng(rng.gen::<usize>()));
+    let not = Not::new(build_rng(rng.gen::<usize>()));
+    let or = Or::new(build_rng(rng.gen::<usize>()));
+    let ori = Ori::new(build_rng(rng.gen::<usize>()), values);
+    let sar_reg = SarReg::new(build_rng(rng.gen::<usize>()));
+    let sar_imm = SarImm::new(build_rng(rng.gen::<usize>()), values);
+    let setf = Setf::new(build_rng(rng.gen::<usize>()));
+    let shl_reg = ShlReg::new(build_rng(rng.gen::<usize>()));
+    let shl_imm = ShlImm::new(build_rng(rng.gen::<usize>()), values);
+    let shr_reg = ShrReg::new(build_rng(rng.gen::<usize>()));
+    let shr_imm = ShrImm::new(build_rng(rng.gen::<usize>()), values);
+    let sub = Sub::new(build_rng(rng.gen::<usize>()));
+    let xor = Xor::new(build_rng(rng.gen::<usize>()));
+    let xori = Xori::new(build_rng(rng.gen::<usize>()), values);
+    let add_reg = AddReg::new(build_rng(rng.gen::<usize>()));
+    let add_imm = AddImm::new(build_rng(rng.gen::<usize>()), values);
+    let addi = AddI::new(build_rng(rng.gen::<usize>()), values);
+    let and = And::new(build_rng(rng.gen::<usize>()));
+    let andi = AndI::new(build_rng(rng.gen::<usize>()), values);
+    let cmp_reg = CmpReg::new(build_rng(rng.gen::<usize>()));
+    let cmp_imm = CmpImm::new(build_rng(rng.gen::<usize>()), values);
+    let mpyhw = Mpyhw::new(build_rng(rng.gen::<usize>()));
+    let rev = Rev::new(build_rng(rng.gen::<usize>()));
+    let xb = Xb::new(build_rng(rng.gen::<usize>()));
+    let xh = Xh::new(build_rng(rng.gen::<usize>()));
+    let div = Div::new(build_rng(rng.gen::<usize>()));
+    let divu = Divu::new(build_rng(rng.gen::<usize>()));
+    let jal_next = JalNext;
+    let mut gen = MultiGenerator::named(vec![
+        ("mul".into(), Box::new(mul) as Box<Generator>),
+        ("stsr_psw".into(), Box::new(stsr_psw) as Box<Generator>),
+        ("movea".into(), Box::new(movea) as Box<Generator>),
+        ("movhi".into(), Box::new(movhi) as Box<Generator>),
+        ("mov_reg".into(), Box::new(mov_reg) as Box<Generator>),
+        ("mov_imm".into(), Box::new(mov_imm) as Box<Generator>),
+        ("mulu".into(), Box::new(mulu) as Box<Generator>),
+        ("not".into(), Box::new(not) as Box<Generator>),
+        ("or".into(), Box::new(or) as Box<Generator>),
+        ("ori".into(), Box::new(ori) as Box<Generator>),
+        ("sar_reg".into(), Box::new(sar_reg) as Box<Generator>),
+        ("sar_imm".into(), Box::new(sar_imm) as Box<Generator>),
+        ("setf".into(), Box::new(setf) as Box<Generator>),
+        ("shl_reg".into(), Box::new(shl_reg) as Box<Generator>),
+        ("shl_imm".into(), Box::new(shl_imm) as Box<Generator>),
+        ("shr_reg".into(), Box::new(shr_reg) as Box<Generator>),
+        ("shr_imm".into(), Box::new(shr_imm) as Box<Generator>),
+        ("sub".into(), Box::new(sub) as Box<Generator>),
+        ("xor".into(), Box::new(xor) as Box<Generator>),
+        ("xori".into(), Box::new(xori) as Box<Generator>),
+        ("add_reg".into(), Box::new(add_reg) as Box<Generator>),
+        ("add_imm".into(), Box::new(add_imm) as Box<Generator>),
+        ("addi".into(), Box::new(addi) as Box<Generator>),
+        ("and".into(), Box::new(and) as Box<Generator>),
+        ("andi".into(), Box::new(andi) as Box<Generator>),
+        ("cmp_reg".into(), Box::new(cmp_reg) as Box<Generator>),
+        ("cmp_imm".into(), Box::new(cmp_imm) as Box<Generator>),
+        ("mpyhw".into(), Box::new(mpyhw) as Box<Generator>),
+        ("rev".into(), Box::new(rev) as Box<Generator>),
+        ("xb".into(), Box::new(xb) as Box<Generator>),
+        ("xh".into(), Box::new(xh) as Box<Generator>),
+        ("div".into(), Box::new(div) as Box<Generator>),
+        ("divu".into(), Box::new(divu) as Box<Generator>),
+        ("jal_next".into(), Box::new(jal_next) as Box<Generator>),
+    ], build_rng(rng.gen::<usize>()));
+    gen.apply_mix(mix).expect("Mix should have been validated");
+    gen
+}
+
+pub fn build_rng(seed: usize) -> StdRng {
+    let seed: &[_] = &[seed];
+    SeedableRng::from_seed(seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mixes_that_overflow_are_rejected() {
+        assert!(Mix::parse("default 0\nadd_reg 4294967295\nadd_imm 1").is_err());
+        assert!(Mix::parse("default 0\nadd_reg 4294967295").is_ok());
+    }
+
+    #[test]
+    fn weights_never_leave_nothing_to_pick() {
+        let mut gen = build_all_generator(&mut build_rng(0), &Mix::parse("default 0\nadd_reg 1").unwrap(), ValueStrategy::Uniform);
+        assert!(gen.set_weight("add_reg", 0).is_err());
+        assert!(gen.set_weight("add_imm", u32::max_value()).is_err());
+        assert_eq!(gen.weight("add_reg"), Some(1));
+        assert_eq!(gen.weight("add_imm"), Some(0));
+
+        let mut buf = Vec::new();
+        gen.next(&mut buf);
+        assert_eq!(gen.picks().into_iter().filter(|&(_, picks)| picks > 0).collect::<Vec<_>>(), vec![("add_reg", 1)]);
+    }
+}
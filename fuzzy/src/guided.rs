@@ -0,0 +1,153 @@
+use command::ExecuteResult;
+use coverage;
+use generators::MultiGenerator;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+// Scores drift back towards neutral by this factor per candidate, so early luck doesn't lock the mix in
+const SCORE_DECAY: f64 = 0.98;
+const SCORE_REWARD: f64 = 0.5;
+const MAX_SCORE: f64 = 20.0;
+
+const MAX_CORPUS_ENTRIES: usize = 256;
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+enum Feature {
+    FlagOutcome(&'static str, u32),
+    OpcodePair(&'static str, &'static str),
+    // Psw status bits (FPU flags, ID, AE, EP, NP) when the program completed, None if it timed out
+    Outcome(Option<u32>),
+}
+
+pub struct CorpusEntry {
+    pub rom: Vec<u8>,
+    pub initial_regs: Vec<u32>,
+    // Number of features this entry saw first
+    pub novelty: usize,
+}
+
+// Feedback loop for coverage-guided generation. Candidates are screened on one target (normally the emulator), and
+//  only the ones that do something we haven't seen before are worth comparing against the rest. Generators that
+//  produced novel programs get their weights bumped.
+pub struct Guide {
+    screen_target: String,
+
+    seen: BTreeSet<Feature>,
+    corpus: Vec<CorpusEntry>,
+    scores: BTreeMap<String, f64>,
+
+    num_candidates: u64,
+    num_novel: u64,
+}
+
+impl Guide {
+    pub fn new<N: Into<String>>(screen_target: N) -> Guide {
+        Guide {
+            screen_target: screen_target.into(),
+
+            seen: BTreeSet::new(),
+            corpus: Vec::new(),
+            scores: BTreeMap::new(),
+
+            num_candidates: 0,
+            num_novel: 0,
+        }
+    }
+
+    pub fn screen_target(&self) -> &str {
+        &self.screen_target
+    }
+
+    pub fn corpus(&self) -> &[CorpusEntry] {
+        &self.corpus
+    }
+
+    // Scales the generator's current weights by what we've learned, so mix presets still apply underneath
+    pub fn tune(&self, gen: &mut MultiGenerator) {
+        let names = gen.names().iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        for name in names.iter() {
+            let weight = gen.weight(name).unwrap_or(0);
+            if weight == 0 {
+                continue;
+            }
+
+            let score = self.scores.get(name).cloned().unwrap_or(1.0);
+            let tuned_weight = ((weight as f64) * score * 10.0).round().max(1.0) as u32;
+            // Weights too big to add up keep their untuned value
+            gen.set_weight(name, tuned_weight).ok();
+        }
+    }
+
+    // Records a screened candidate, returning how many new features it hit. picks are the generator pick counts
+    //  that produced it (empty for candidates that didn't come from a generator).
+    pub fn observe(&mut self, rom: &[u8], initial_regs: &[u32], result: &ExecuteResult, picks: &[(String, u64)]) -> usize {
+        self.num_candidates += 1;
+
+        let novelty = features(rom, result).into_iter().filter(|feature| self.seen.insert(feature.clone())).count();
+
+        for score in self.scores.values_mut() {
+            *score = 1.0 + (*score - 1.0) * SCORE_DECAY;
+        }
+
+        if novelty == 0 {
+            return 0;
+        }
+
+        self.num_novel += 1;
+
+        let total_picks = picks.iter().map(|&(_, picks)| picks).sum::<u64>();
+        if total_picks > 0 {
+            for &(ref name, picks) in picks.iter() {
+                let score = self.scores.entry(name.clone()).or_insert(1.0);
+                *score = (*score + SCORE_REWARD * (novelty as f64) * (picks as f64) / (total_picks as f64)).min(MAX_SCORE);
+            }
+        }
+
+        if self.corpus.len() >= MAX_CORPUS_ENTRIES {
+            // Make room by dropping the least interesting entry
+            let least_novel = (0..self.corpus.len()).min_by_key(|&index| self.corpus[index].novelty).unwrap();
+            self.corpus.remove(least_novel);
+        }
+        self.corpus.push(CorpusEntry {
+            rom: rom.to_vec(),
+            initial_regs: initial_regs.to_vec(),
+            novelty: novelty,
+        });
+
+        novelty
+    }
+
+    pub fn summary(&self) -> String {
+        let mut scores = self.scores.iter().collect::<Vec<_>>();
+        scores.sort_by(|a, b| b.1.partial_cmp(a.1).unwrap());
+        let favoured = scores.iter().take(5).map(|&(name, score)| format!("{} x{:.1}", name, score)).collect::<Vec<_>>();
+
+        format!("Guided: {} candidates screened on `{}`, {} novel, {} features seen, {} corpus entries, favouring {}",
+            self.num_candidates,
+            self.screen_target,
+            self.num_novel,
+            self.seen.len(),
+            self.corpus.len(),
+            if favoured.is_empty() { String::from("nothing yet") } else { favoured.join(", ") })
+    }
+}
+
+fn features(rom: &[u8], result: &ExecuteResult) -> Vec<Feature> {
+    let instructions = coverage::program_instructions(rom);
+
+    let mut ret = instructions.windows(2)
+        .map(|pair| Feature::OpcodePair(pair[0].name(), pair[1].name()))
+        .collect::<Vec<_>>();
+
+    match result {
+        &ExecuteResult::Completed { ref regs, .. } => {
+            ret.extend(coverage::flag_observations(&instructions, regs).into_iter().map(|(name, flags)| Feature::FlagOutcome(name, flags)));
+            if let Some(&psw) = regs.get(32) {
+                ret.push(Feature::Outcome(Some(psw & 0xfff0)));
+            }
+        }
+        &ExecuteResult::TimedOut => ret.push(Feature::Outcome(None)),
+    }
+
+    ret
+}
@@ -0,0 +1,191 @@
+use rand::{Rng, StdRng};
+
+use asm::*;
+use generators::Generator;
+
+// Loads read back what was just stored in the scratch region (see memory.rs), so they never see memory that could
+//  differ between targets
+const SCRATCH_ADDR_HI: u16 = 0x0501;
+// 0x05010000 - 0x8000 = 0x05008000
+const SCRATCH_ADDR_LO: i16 = -0x8000;
+const SCRATCH_WORDS: u16 = 64;
+
+// PSW flags (Z, S, OV, CY) and FPU flags. ldsr psw writes the whole psw, so masking the value down to these
+//  deliberately clears everything above them (ID, AE, EP, NP, I), keeping interrupts enabled and the CPU out of
+//  exception states for the rest of the test.
+const PSW_FLAG_BITS: u16 = 0x03ff;
+
+// Ops that set the flags from two regs, and ops that read two regs, for the patterns to pick from
+const FLAG_OPS: [fn(&mut Vec<u8>, u16, u16); 7] = [add, sub, cmp, shl, or, and, xor];
+const CONSUMER_OPS: [fn(&mut Vec<u8>, u16, u16); 8] = [add, sub, cmp, shl, or, and, xor, mul];
+// With whether the op divides
+const MUL_DIV_OPS: [(fn(&mut Vec<u8>, u16, u16), bool); 4] = [(mul, false), (mulu, false), (div, true), (divu, true)];
+
+// Holds mul/div's high word/remainder
+const MUL_DIV_HIGH_REG: u16 = 30;
+const LINK_REG: u16 = 31;
+
+const MAX_CONTEXT_INSTRUCTIONS: u32 = 3;
+const MAX_CHAIN_LEN: u32 = 4;
+const MAX_SKIPPED_INSTRUCTIONS: u32 = 4;
+
+// Emits a few instructions of random context from another generator, then one short sequence that's known to be
+//  hard on a pipeline:
+//  - a load whose result is used by the very next instruction
+//  - a flag-setting op followed right away by bcond or setf
+//  - multiplies and divides back to back, often feeding each other
+//  - ldsr psw followed right away by something that reads the flags
+//  - jmp through a reg written by the instruction right before it
+//
+// Everything a pattern does lands in regs (or the scratch region), so it's compared like any other result. Roms
+//  full of these are also worth timing, since stalls are exactly what pipeline emulation tends to get wrong.
+pub struct HazardGenerator {
+    context: Box<Generator>,
+    rng: StdRng,
+}
+
+impl HazardGenerator {
+    pub fn new(context: Box<Generator>, rng: StdRng) -> HazardGenerator {
+        HazardGenerator {
+            context: context,
+            rng: rng,
+        }
+    }
+
+    fn random_reg(&mut self) -> u16 {
+        self.rng.gen_range(1, 32)
+    }
+
+    // A reg mul/div (and jal) won't overwrite as a side effect
+    fn random_plain_reg(&mut self) -> u16 {
+        self.rng.gen_range(1, MUL_DIV_HIGH_REG)
+    }
+
+    fn load_use(&mut self, buf: &mut Vec<u8>) {
+        let base = self.random_plain_reg();
+        let src = self.random_reg();
+        let dst = self.random_reg();
+        let other = self.random_reg();
+        let disp = (self.rng.gen_range(0, SCRATCH_WORDS) * 4) as i16;
+
+        movhi(buf, SCRATCH_ADDR_HI, 0, base);
+        movea(buf, SCRATCH_ADDR_LO, base, base);
+        st_w(buf, src, disp, base);
+        match self.rng.gen::<u32>() % 3 {
+            0 => ld_b(buf, disp + self.rng.gen_range(0, 4), base, dst),
+            1 => ld_h(buf, disp + self.rng.gen_range(0, 2) * 2, base, dst),
+            _ => ld_w(buf, disp, base, dst),
+        }
+        let op = *self.rng.choose(&CONSUMER_OPS).unwrap();
+        if self.rng.gen() {
+            op(buf, dst, other);
+        } else {
+            op(buf, other, dst);
+        }
+    }
+
+    fn flag_consumer(&mut self, buf: &mut Vec<u8>) {
+        let cond = self.rng.gen_range(0, 16);
+        let dst = self.random_reg();
+        match self.rng.gen::<u32>() % 3 {
+            // Taken or not shows up in whether the mov happened
+            0 => {
+                bcond(buf, cond, 4);
+                mov_imm(buf, self.rng.gen_range(-16, 16), dst);
+            }
+            1 => setf(buf, cond, dst),
+            _ => stsr(buf, PSW, dst),
+        }
+    }
+
+    fn flags_then_branch(&mut self, buf: &mut Vec<u8>) {
+        let reg1 = self.random_reg();
+        let reg2 = self.random_reg();
+        let op = *self.rng.choose(&FLAG_OPS).unwrap();
+        op(buf, reg1, reg2);
+        self.flag_consumer(buf);
+    }
+
+    fn mul_div_chain(&mut self, buf: &mut Vec<u8>) {
+        let len = self.rng.gen_range(2, MAX_CHAIN_LEN + 1);
+
+        // Divisors are picked up front and made odd, and nothing in the chain writes them, so none of the divides
+        //  trap. Products and quotients go elsewhere.
+        let mut ops = Vec::new();
+        let mut divisors = Vec::new();
+        for _ in 0..len {
+            let (op, is_div) = *self.rng.choose(&MUL_DIV_OPS).unwrap();
+            let reg1 = if is_div {
+                let reg1 = self.random_plain_reg();
+                divisors.push(reg1);
+                Some(reg1)
+            } else {
+                None
+            };
+            ops.push((op, reg1));
+        }
+        for &divisor in divisors.iter() {
+            ori(buf, 1, divisor, divisor);
+        }
+
+        let mut last_reg2 = None;
+        for (op, reg1) in ops {
+            let reg1 = match reg1 {
+                Some(reg1) => reg1,
+                // Often the previous result (or its high word), so the ops depend on each other
+                _ => match (last_reg2, self.rng.gen::<u32>() % 3) {
+                    (Some(reg2), 0) => reg2,
+                    (Some(_), 1) => MUL_DIV_HIGH_REG,
+                    _ => self.random_reg(),
+                },
+            };
+            let reg2 = loop {
+                let reg2 = self.random_plain_reg();
+                if !divisors.contains(&reg2) {
+                    break reg2;
+                }
+            };
+            op(buf, reg1, reg2);
+            last_reg2 = Some(reg2);
+        }
+    }
+
+    fn ldsr_psw_then_flags(&mut self, buf: &mut Vec<u8>) {
+        let src = self.random_reg();
+        let value = self.random_reg();
+        andi(buf, PSW_FLAG_BITS, src, value);
+        ldsr(buf, value, PSW);
+        self.flag_consumer(buf);
+    }
+
+    fn jump_through_written_reg(&mut self, buf: &mut Vec<u8>) {
+        let dst = self.random_plain_reg();
+        let num_skipped = self.rng.gen_range(0, MAX_SKIPPED_INSTRUCTIONS + 1);
+
+        // jal leaves the movea's addr in r31. The jmp lands past the movea (4), itself (2) and the skipped
+        //  instructions, which would show up in the regs if they ran.
+        jal(buf, 4);
+        movea(buf, (4 + 2 + num_skipped * 2) as i16, LINK_REG, dst);
+        jmp(buf, dst);
+        for _ in 0..num_skipped {
+            let reg = self.random_reg();
+            mov_imm(buf, self.rng.gen_range(-16, 16), reg);
+        }
+    }
+}
+
+impl Generator for HazardGenerator {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        for _ in 0..self.rng.gen_range(0, MAX_CONTEXT_INSTRUCTIONS + 1) {
+            self.context.next(buf);
+        }
+
+        match self.rng.gen::<u32>() % 5 {
+            0 => self.load_use(buf),
+            1 => self.flags_then_branch(buf),
+            2 => self.mul_div_chain(buf),
+            3 => self.ldsr_psw_then_flags(buf),
+            _ => self.jump_through_written_reg(buf),
+        }
+    }
+}
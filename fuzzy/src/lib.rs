@@ -0,0 +1,40 @@
+extern crate serialport;
+extern crate rustual_boy_core;
+extern crate rustual_boy_middleware;
+extern crate rand;
+extern crate time;
+extern crate byteorder;
+extern crate minifb;
+
+// Targets and the loader protocol they speak
+pub mod command;
+pub mod emu;
+pub mod remote;
+pub mod target;
+pub mod teensy_vb;
+pub mod threaded;
+
+// Generating test roms and running them across targets
+pub mod generators;
+pub mod runner;
+
+mod artifacts;
+mod asm;
+mod cache;
+mod coverage;
+mod crapsum;
+mod dataflow;
+mod disasm;
+mod guided;
+mod hazards;
+mod memory;
+mod mismatch;
+mod placement;
+mod report;
+mod suite;
+mod timer;
+mod trace;
+mod transport;
+mod values;
+mod vip;
+mod vram;
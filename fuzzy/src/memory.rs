@@ -0,0 +1,195 @@
+use command;
+use target::Target;
+
+#[derive(Clone, Debug)]
+pub struct MemRegion {
+    pub name: String,
+    pub addr: u32,
+    pub len: u32,
+}
+
+// Bytes that legitimately differ between targets (or between runs), so they're never compared
+const VOLATILE_REGIONS: &'static [(&'static str, u32, u32)] = &[
+    // Link buffers and other loader state, below the rom addr
+    ("loader globals", 0x05000000, 0x0400),
+    // Everything the loader's stack can reach at the top of WRAM
+    ("loader stack", 0x0500e000, 0x2000),
+    // Initial regs, results including elapsed ticks (which are compared separately anyway), and the timer
+    //  interrupt record
+    ("harness regs", 0x0001e000, 0x0200),
+];
+
+pub const REGION_PRESETS: &'static [&'static str] = &["scratch", "wram"];
+
+// Only this many differing ranges are listed, and only this many bytes of each
+const MAX_LISTED_RANGES: usize = 16;
+const MAX_LISTED_BYTES: usize = 16;
+
+impl MemRegion {
+    pub fn new<N: Into<String>>(name: N, addr: u32, len: u32) -> MemRegion {
+        MemRegion {
+            name: name.into(),
+            addr: addr,
+            len: len,
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<MemRegion> {
+        match name {
+            // Past the test roms, for memory-touching tests to use
+            "scratch" => Some(MemRegion::new(name, 0x05008000, 0x1000)),
+            // All of it; slow to read back over the link
+            "wram" => Some(MemRegion::new(name, 0x05000000, 0x10000)),
+            _ => None,
+        }
+    }
+
+    // Either a preset or <addr>:<len>, both in hex (with 0x) or decimal
+    pub fn parse(spec: &str) -> Result<MemRegion, String> {
+        if let Some(region) = MemRegion::preset(spec) {
+            return Ok(region);
+        }
+
+        let parts = spec.split(':').collect::<Vec<_>>();
+        if parts.len() != 2 {
+            return Err(format!("Expected a preset ({}) or <addr>:<len>, got `{}`", REGION_PRESETS.join(", "), spec));
+        }
+        let addr = parse_number(parts[0])?;
+        let len = parse_number(parts[1])?;
+        if len == 0 {
+            return Err(format!("Region `{}` is empty", spec));
+        }
+        if addr.checked_add(len).is_none() {
+            return Err(format!("Region `{}` runs past the end of the address space", spec));
+        }
+        Ok(MemRegion::new(spec, addr, len))
+    }
+}
+
+// Comma-separated regions
+pub fn parse_regions(spec: &str) -> Result<Vec<MemRegion>, String> {
+    spec.split(',').map(|region| MemRegion::parse(region.trim())).collect()
+}
+
+fn parse_number(s: &str) -> Result<u32, String> {
+    let ret = if s.starts_with("0x") {
+        u32::from_str_radix(&s[2..], 16)
+    } else {
+        s.parse()
+    };
+    ret.map_err(|e| format!("Invalid number `{}`: {}", s, e))
+}
+
+fn is_volatile(addr: u32) -> bool {
+    VOLATILE_REGIONS.iter().any(|&(_, start, len)| addr >= start && addr - start < len)
+}
+
+// Fills each region with a pattern that only depends on the address, so whatever earlier tests left there (hardware
+//  WRAM survives resets) can't show up as a difference. Volatile bytes are left alone, since the loader lives there.
+pub fn fill(target: &mut Box<Target>, regions: &[MemRegion]) -> Result<(), command::Error> {
+    for region in regions.iter() {
+        let end = region.addr + region.len;
+        let mut addr = region.addr;
+        while addr < end {
+            if is_volatile(addr) {
+                addr += 1;
+                continue;
+            }
+
+            let start = addr;
+            while addr < end && !is_volatile(addr) {
+                addr += 1;
+            }
+            target.upload(start, &(start..addr).map(fill_byte).collect::<Vec<_>>())?;
+        }
+    }
+    Ok(())
+}
+
+fn fill_byte(addr: u32) -> u8 {
+    (addr ^ (addr >> 8)) as u8
+}
+
+// Reads back each region, with volatile bytes zeroed out so they always compare equal
+pub fn capture(target: &mut Box<Target>, regions: &[MemRegion]) -> Result<Vec<Vec<u8>>, command::Error> {
+    regions.iter().map(|region| {
+        let mut data = target.read_mem(region.addr, region.len)?;
+        for (offset, byte) in data.iter_mut().enumerate() {
+            if is_volatile(region.addr + offset as u32) {
+                *byte = 0;
+            }
+        }
+        Ok(data)
+    }).collect()
+}
+
+// Groups targets whose captures agree, and lists the address ranges where they don't with each target's bytes.
+//  None if everything agrees.
+pub fn diff(names: &[String], captures: &[Vec<Vec<u8>>], regions: &[MemRegion]) -> Option<(Vec<Vec<String>>, String)> {
+    let mut groups: Vec<(&Vec<Vec<u8>>, Vec<String>)> = Vec::new();
+    for (name, capture) in names.iter().zip(captures.iter()) {
+        match groups.iter().position(|&(group_capture, _)| group_capture == capture) {
+            Some(group_index) => groups[group_index].1.push(name.clone()),
+            None => groups.push((capture, vec![name.clone()])),
+        }
+    }
+    if groups.len() <= 1 {
+        return None;
+    }
+
+    let mut ranges = Vec::new();
+    for (region_index, region) in regions.iter().enumerate() {
+        let differs = |offset: usize| captures.iter().any(|capture| capture[region_index][offset] != captures[0][region_index][offset]);
+
+        let mut offset = 0;
+        while offset < region.len as usize {
+            if !differs(offset) {
+                offset += 1;
+                continue;
+            }
+
+            let start = offset;
+            while offset < region.len as usize && differs(offset) {
+                offset += 1;
+            }
+            ranges.push((region_index, start, offset));
+        }
+    }
+
+    let mut details = format!("memory ({}): {} differing ranges", names.join(", "), ranges.len());
+    for &(region_index, start, end) in ranges.iter().take(MAX_LISTED_RANGES) {
+        let region = &regions[region_index];
+        let values = names.iter().zip(captures.iter()).map(|(name, capture)| {
+            let bytes = &capture[region_index][start..end];
+            let mut formatted = bytes.iter().take(MAX_LISTED_BYTES).map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(" ");
+            if bytes.len() > MAX_LISTED_BYTES {
+                formatted += " ...";
+            }
+            format!("{} {}", name, formatted)
+        }).collect::<Vec<_>>();
+        details += &format!("\n  {} 0x{:08x}-0x{:08x} ({} bytes): {}",
+            region.name,
+            region.addr + start as u32,
+            region.addr + end as u32 - 1,
+            end - start,
+            values.join(" | "));
+    }
+    if ranges.len() > MAX_LISTED_RANGES {
+        details += &format!("\n  ... and {} more", ranges.len() - MAX_LISTED_RANGES);
+    }
+
+    Some((groups.into_iter().map(|(_, names)| names).collect(), details))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_must_fit_in_the_address_space() {
+        assert!(MemRegion::parse("0xfffff000:0x1000").unwrap_err().contains("past the end"));
+        assert!(MemRegion::parse("0xffffffff:1").is_err());
+        assert!(MemRegion::parse("0xfffff000:0xfff").is_ok());
+        assert!(MemRegion::parse("0x05000000:0").is_err());
+    }
+}
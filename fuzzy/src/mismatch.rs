@@ -0,0 +1,197 @@
+use command::ExecuteResult;
+
+// Result slot holding the psw (see ExecuteResult)
+pub const PSW_SLOT: usize = 32;
+
+const PSW_FLAGS: &'static [(&'static str, u32)] = &[
+    ("Z", 1 << 0),
+    ("S", 1 << 1),
+    ("OV", 1 << 2),
+    ("CY", 1 << 3),
+    ("FPR", 1 << 4),
+    ("FUD", 1 << 5),
+    ("FOV", 1 << 6),
+    ("FZD", 1 << 7),
+    ("FIV", 1 << 8),
+    ("FRO", 1 << 9),
+    ("ID", 1 << 12),
+    ("AE", 1 << 13),
+    ("EP", 1 << 14),
+    ("NP", 1 << 15),
+];
+const PSW_I_SHIFT: u32 = 16;
+const PSW_I_MASK: u32 = 0x0f << PSW_I_SHIFT;
+
+pub fn slot_name(slot: usize) -> String {
+    match slot {
+        PSW_SLOT => String::from("psw"),
+        3 => String::from("r3/sp"),
+        4 => String::from("r4/gp"),
+        5 => String::from("r5/tp"),
+        31 => String::from("r31/lp"),
+        _ => format!("r{}", slot),
+    }
+}
+
+// Set flags by name, then the interrupt level, eg. "Z CY ID I=0"
+pub fn decode_psw(psw: u32) -> String {
+    let mut ret = PSW_FLAGS.iter().filter(|&&(_, mask)| psw & mask != 0).map(|&(name, _)| name.to_string()).collect::<Vec<_>>();
+    ret.push(format!("I={}", (psw & PSW_I_MASK) >> PSW_I_SHIFT));
+    ret.join(" ")
+}
+
+// One line per result slot, with every target's value. Values that differ from the first target's are flagged
+//  with !! and get the bits that differ (as an xor mask) and anything that looks like a likely cause.
+pub fn describe(names: &[String], results: &[ExecuteResult]) -> String {
+    let mut lines = Vec::new();
+
+    let timed_out = names.iter().zip(results.iter())
+        .filter(|&(_, result)| *result == ExecuteResult::TimedOut)
+        .map(|(name, _)| format!("`{}`", name))
+        .collect::<Vec<_>>();
+    if !timed_out.is_empty() {
+        lines.push(format!("  timed out: {}", timed_out.join(", ")));
+    }
+
+    let (completed_names, regs): (Vec<_>, Vec<_>) = names.iter().zip(results.iter()).filter_map(|(name, result)| match result {
+        &ExecuteResult::Completed { ref regs, .. } => Some((name, regs)),
+        &ExecuteResult::TimedOut => None,
+    }).unzip();
+    let num_slots = regs.iter().map(|regs| regs.len()).max().unwrap_or(0);
+
+    if num_slots > 0 {
+        // Values are 10 chars wide
+        let header = completed_names.iter().enumerate()
+            .map(|(index, name)| if index + 1 < completed_names.len() { format!("{:<10}", name) } else { name.to_string() })
+            .collect::<Vec<_>>();
+        lines.push(format!("     {:<7} {}", "", header.join(" ")));
+    }
+
+    for slot in 0..num_slots {
+        let values = regs.iter().map(|regs| regs[slot]).collect::<Vec<_>>();
+        let reference = values[0];
+        let all_match = values.iter().all(|&value| value == reference);
+
+        let formatted_values = values.iter().map(|value| format!("0x{:08x}", value)).collect::<Vec<_>>();
+        let mut line = format!("  {} {:<7} {}", if all_match { "  " } else { "!!" }, slot_name(slot), formatted_values.join(" "));
+
+        if slot == PSW_SLOT {
+            let decoded = values.iter().map(|&value| format!("[{}]", decode_psw(value))).collect::<Vec<_>>();
+            line += &format!("  {}", decoded.join(" "));
+        }
+
+        for &value in values.iter().filter(|&&value| value != reference) {
+            line += &format!("  xor 0x{:08x}", reference ^ value);
+            let hints = if slot == PSW_SLOT { psw_hints(reference, value) } else { value_hints(reference, value) };
+            if !hints.is_empty() {
+                line += &format!(" ({})", hints.join(", "));
+            }
+        }
+
+        lines.push(line);
+    }
+
+    lines.join("\n")
+}
+
+fn psw_hints(a: u32, b: u32) -> Vec<String> {
+    let diff = a ^ b;
+    let differing = PSW_FLAGS.iter().filter(|&&(_, mask)| diff & mask != 0).map(|&(name, _)| name).collect::<Vec<_>>();
+
+    let mut ret = Vec::new();
+    match differing.len() {
+        0 => (),
+        1 => ret.push(format!("only {} differs", differing[0])),
+        _ => ret.push(format!("{} differ", differing.join("/"))),
+    }
+    if diff & PSW_I_MASK != 0 {
+        ret.push(String::from("interrupt level differs"));
+    }
+    ret
+}
+
+fn value_hints(a: u32, b: u32) -> Vec<String> {
+    let diff = a ^ b;
+
+    let mut ret = Vec::new();
+    if a.wrapping_sub(b) == 1 || b.wrapping_sub(a) == 1 {
+        ret.push(String::from("off by one"));
+    } else if diff.count_ones() == 1 {
+        ret.push(format!("only bit {} differs", diff.trailing_zeros()));
+    }
+    if a == b.rotate_left(16) {
+        ret.push(String::from("halfwords swapped"));
+    }
+    if a == b.swap_bytes() {
+        ret.push(String::from("bytes reversed"));
+    }
+    if let Some(hint) = extension_hint(a, b).or_else(|| extension_hint(b, a)) {
+        ret.push(hint);
+    }
+    ret
+}
+
+// Spots the same low bits extended differently: sign vs zero extension, or sign extension from the wrong bit
+fn extension_hint(a: u32, b: u32) -> Option<String> {
+    for &width in [8, 16].iter() {
+        if a != b && a == sign_extend(a, width) && b == zero_extend(a, width) {
+            return Some(format!("sign vs zero extension of the low {} bits", width));
+        }
+    }
+    for width in 2..32 {
+        if a != b && a == sign_extend(a, width) && b == sign_extend(b, width + 1) && zero_extend(a, width) == zero_extend(b, width) {
+            return Some(format!("sign extended from bit {} vs bit {}", width - 1, width));
+        }
+    }
+    None
+}
+
+fn sign_extend(value: u32, width: u32) -> u32 {
+    (((value << (32 - width)) as i32) >> (32 - width)) as u32
+}
+
+fn zero_extend(value: u32, width: u32) -> u32 {
+    value & (((1u64 << width) - 1) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_vs_zero_extension() {
+        assert_eq!(value_hints(0xffffff80, 0x80), vec!["sign vs zero extension of the low 8 bits"]);
+        assert_eq!(value_hints(0x80, 0xffffff80), vec!["sign vs zero extension of the low 8 bits"]);
+        assert_eq!(value_hints(0xffff8000, 0x8000), vec!["sign vs zero extension of the low 16 bits"]);
+        // Positive values extend the same either way
+        assert_eq!(extension_hint(0x7f, 0x7f), None);
+    }
+
+    #[test]
+    fn sign_extension_from_the_wrong_bit() {
+        assert_eq!(extension_hint(0xfffffff0, 0x10), Some(String::from("sign extended from bit 4 vs bit 5")));
+    }
+
+    #[test]
+    fn off_by_one() {
+        assert_eq!(value_hints(5, 6), vec!["off by one"]);
+        assert_eq!(value_hints(0, 0xffffffff), vec!["off by one"]);
+        assert_eq!(value_hints(0x100, 0x101), vec!["off by one"]);
+        assert_eq!(value_hints(0x100, 0x110), vec!["only bit 4 differs"]);
+    }
+
+    #[test]
+    fn swapped_halfwords_and_bytes() {
+        assert_eq!(value_hints(0x12345678, 0x56781234), vec!["halfwords swapped"]);
+        assert_eq!(value_hints(0x12345678, 0x78563412), vec!["bytes reversed"]);
+    }
+
+    #[test]
+    fn psw_flags_and_interrupt_level() {
+        assert_eq!(decode_psw(0), "I=0");
+        assert_eq!(decode_psw(0x00009009), "Z CY ID NP I=0");
+        assert_eq!(decode_psw(0x000f0002), "S I=15");
+        // Bits above the interrupt level aren't decoded
+        assert_eq!(decode_psw(0x00f50000), "I=5");
+    }
+}
@@ -0,0 +1,154 @@
+use rand::{Rng, SeedableRng, StdRng};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use crapsum::Crapsum;
+use memory::MemRegion;
+
+// Where roms go unless code regions say otherwise: WRAM, right past the loader's globals
+pub const DEFAULT_ROM_ADDR: u32 = 0x05000000 + 0x0400;
+
+pub const CODE_REGION_PRESETS: &'static [&'static str] = &["wram", "vram"];
+
+// Roms land on at least halfword boundaries (instructions need that much), and often on coarser ones
+const ALIGNMENTS: &'static [u32] = &[2, 4, 16, 256];
+
+// Writable regions the V810 can fetch from, each with its own bus width and wait states
+pub fn code_region_preset(name: &str) -> Option<MemRegion> {
+    match name {
+        // From the default rom addr up to the scratch region (see memory.rs)
+        "wram" => Some(MemRegion::new(name, DEFAULT_ROM_ADDR, 0x05008000 - DEFAULT_ROM_ADDR)),
+        // BGMaps 8-13, which nothing draws from. The loader's console and rendered scenes stay well below.
+        "vram" => Some(MemRegion::new(name, 0x00030000, 0xc000)),
+        _ => None,
+    }
+}
+
+// Comma-separated presets or <addr>:<len> regions
+pub fn parse_code_regions(spec: &str) -> Result<Vec<MemRegion>, String> {
+    spec.split(',').map(|region| {
+        let region = region.trim();
+        match code_region_preset(region) {
+            Some(region) => Ok(region),
+            _ if region.contains(':') => MemRegion::parse(region),
+            _ => Err(format!("Expected a preset ({}) or <addr>:<len>, got `{}`", CODE_REGION_PRESETS.join(", "), region)),
+        }
+    }).collect()
+}
+
+// Picks a region the rom fits in, and a randomly aligned offset into it. The choice is derived from the case itself,
+//  so every run of a case (eg. screening it on one target first, or tracing it later) puts it in the same place.
+//  Without any regions (or any the rom fits in), it's the default rom addr.
+pub fn pick_rom_addr(regions: &[MemRegion], rom: &[u8], initial_regs: &[u32]) -> u32 {
+    let fitting = regions.iter().filter(|region| rom.len() as u32 <= region.len).collect::<Vec<_>>();
+    if fitting.is_empty() {
+        return DEFAULT_ROM_ADDR;
+    }
+
+    let mut case_bytes = rom.to_vec();
+    for &reg in initial_regs.iter() {
+        case_bytes.write_u32::<LittleEndian>(reg).unwrap();
+    }
+    let seed: &[_] = &[Crapsum::compute(&case_bytes).state as usize];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+
+    let region = rng.choose(&fitting).unwrap();
+    let end = region.addr + region.len - rom.len() as u32;
+    let alignment = *rng.choose(ALIGNMENTS).unwrap();
+    // Regions given by addr don't have to start on any particular boundary. Coarse alignments that don't leave
+    //  room for the rom fall back to halfwords.
+    let (start, alignment) = match align_up(region.addr, alignment) {
+        Some(start) if start <= end => (start, alignment),
+        _ => match align_up(region.addr, 2) {
+            Some(start) if start <= end => (start, 2),
+            _ => return DEFAULT_ROM_ADDR,
+        },
+    };
+    start + rng.gen_range(0, (end - start) / alignment + 1) * alignment
+}
+
+// None if that would go past the top of the address space
+fn align_up(addr: u32, alignment: u32) -> Option<u32> {
+    addr.checked_add(alignment - 1).map(|addr| addr / alignment * alignment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use generators::build_rng;
+
+    fn random_case(seed: usize, max_len: usize) -> (Vec<u8>, Vec<u32>) {
+        let mut rng = build_rng(seed);
+        let len = rng.gen_range(1, max_len / 2 + 1) * 2;
+        let rom = (0..len).map(|_| rng.gen()).collect();
+        let initial_regs = (0..32).map(|_| rng.gen()).collect();
+        (rom, initial_regs)
+    }
+
+    fn assert_inside(regions: &[MemRegion], rom: &[u8], addr: u32) {
+        assert_eq!(addr % 2, 0);
+        assert!(regions.iter().any(|region| addr >= region.addr && addr - region.addr + rom.len() as u32 <= region.len),
+            "0x{:08x} (len 0x{:x}) isn't inside any region", addr, rom.len());
+    }
+
+    #[test]
+    fn same_case_same_addr() {
+        let regions = parse_code_regions("wram,vram").unwrap();
+        for seed in 0..100 {
+            let (rom, initial_regs) = random_case(seed, 0x100);
+            let addr = pick_rom_addr(&regions, &rom, &initial_regs);
+            assert_eq!(pick_rom_addr(&regions, &rom, &initial_regs), addr);
+            assert_eq!(pick_rom_addr(&regions, &rom.clone(), &initial_regs.clone()), addr);
+        }
+    }
+
+    #[test]
+    fn roms_stay_inside_their_region() {
+        let regions = parse_code_regions("wram,vram,0x06000000:0x2000,0x06000003:0x20,0xfffffff1:0xe").unwrap();
+        let mut addrs = Vec::new();
+        for seed in 0..1000 {
+            let (rom, initial_regs) = random_case(seed, 0x2000);
+            let addr = pick_rom_addr(&regions, &rom, &initial_regs);
+            assert_inside(&regions, &rom, addr);
+            addrs.push(addr);
+        }
+        // Different cases actually get spread around
+        addrs.sort();
+        addrs.dedup();
+        assert!(addrs.len() > 500);
+    }
+
+    #[test]
+    fn coarse_alignments_fall_back_to_halfwords() {
+        // Nothing in here is 4-byte aligned with room to spare, let alone 256-byte aligned
+        let regions = vec![MemRegion::new("tight", 0x06000002, 0x12)];
+        for seed in 0..100 {
+            let (mut rom, initial_regs) = random_case(seed, 0x10);
+            rom.resize(0x10, 0);
+            let addr = pick_rom_addr(&regions, &rom, &initial_regs);
+            assert!(addr == 0x06000002 || addr == 0x06000004);
+        }
+
+        // Aligning up at the very top of the address space doesn't wrap around
+        let top = vec![MemRegion::new("top", 0xfffffff1, 0xe)];
+        for seed in 0..100 {
+            let (mut rom, initial_regs) = random_case(seed, 0xc);
+            rom.resize(0xc, 0);
+            assert_eq!(pick_rom_addr(&top, &rom, &initial_regs), 0xfffffff2);
+        }
+    }
+
+    #[test]
+    fn roms_that_fit_nowhere_get_the_default_addr() {
+        let (rom, initial_regs) = random_case(0, 0x10);
+        assert_eq!(pick_rom_addr(&[], &rom, &initial_regs), DEFAULT_ROM_ADDR);
+
+        let too_small = vec![MemRegion::new("too small", 0x06000000, rom.len() as u32 - 2)];
+        assert_eq!(pick_rom_addr(&too_small, &rom, &initial_regs), DEFAULT_ROM_ADDR);
+
+        // Big enough, but not at halfword alignment
+        let misaligned = vec![MemRegion::new("misaligned", 0x06000001, rom.len() as u32)];
+        assert_eq!(pick_rom_addr(&misaligned, &rom, &initial_regs), DEFAULT_ROM_ADDR);
+    }
+}
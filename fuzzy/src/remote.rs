@@ -0,0 +1,299 @@
+use target::Link;
+use transport;
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+// Framing on the socket mirrors the serial link, with a frame type up front and a status byte on the way back:
+//  client -> server: FRAME_EXCHANGE, packet len - 1, packet bytes
+//                    FRAME_RECOVER
+//  server -> client: STATUS_OK, packet len - 1, packet bytes (exchange)
+//                    STATUS_OK (recover)
+//                    STATUS_FAILED (either)
+const FRAME_EXCHANGE: u8 = 0x00;
+const FRAME_RECOVER: u8 = 0x01;
+
+const STATUS_OK: u8 = 0x00;
+const STATUS_FAILED: u8 = 0x01;
+
+pub fn serve<L: Link, A: ToSocketAddrs>(link: &mut L, addr: A) -> Result<(), String> {
+    let listener = TcpListener::bind(addr).map_err(|e| format!("Couldn't bind listener: {}", e))?;
+
+    // Only one client can own the link at a time, so clients are served one after another
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("Couldn't accept client: {}", e);
+                continue;
+            }
+        };
+
+        let peer = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_else(|_| "unknown".into());
+        println!("Client {} connected", peer);
+
+        match serve_client(link, &mut stream) {
+            Ok(()) => println!("Client {} disconnected", peer),
+            Err(e) => println!("Client {} dropped: {}", peer, e),
+        }
+    }
+
+    Ok(())
+}
+
+fn serve_client<L: Link>(link: &mut L, stream: &mut TcpStream) -> io::Result<()> {
+    loop {
+        let mut frame_type = [0];
+        if stream.read(&mut frame_type)? == 0 {
+            // Client hung up
+            return Ok(());
+        }
+
+        match frame_type[0] {
+            FRAME_EXCHANGE => {
+                let mut packet_len = [0];
+                stream.read_exact(&mut packet_len)?;
+                let mut packet = vec![0; (packet_len[0] as usize) + 1];
+                stream.read_exact(&mut packet)?;
+
+                match transport::exchange_packet(link, &packet) {
+                    Ok(received_packet) => {
+                        let response = [STATUS_OK, (received_packet.len() - 1) as u8].iter()
+                            .chain(received_packet.iter())
+                            .cloned()
+                            .collect::<Vec<_>>();
+                        stream.write_all(&response)?;
+                    }
+                    Err(_) => {
+                        stream.write_all(&[STATUS_FAILED])?;
+                    }
+                }
+            }
+            FRAME_RECOVER => {
+                match link.recover() {
+                    Ok(()) => {
+                        stream.write_all(&[STATUS_OK])?;
+                    }
+                    Err(e) => {
+                        println!("Recovery failed: {}", e);
+                        stream.write_all(&[STATUS_FAILED])?;
+                    }
+                }
+            }
+            _ => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown frame type"));
+            }
+        }
+    }
+}
+
+pub struct RemotePort {
+    stream: TcpStream,
+
+    response_buffer: VecDeque<u8>,
+}
+
+impl RemotePort {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<RemotePort, String> {
+        let stream = TcpStream::connect(addr).map_err(|e| format!("Couldn't connect to remote rig: {}", e))?;
+        // Recovery on the server side can take a while, but a dead server shouldn't hang us forever
+        stream.set_read_timeout(Some(Duration::from_secs(60))).map_err(|e| format!("Couldn't set socket timeout: {}", e))?;
+        stream.set_nodelay(true).map_err(|e| format!("Couldn't set socket nodelay: {}", e))?;
+
+        Ok(RemotePort {
+            stream: stream,
+
+            response_buffer: VecDeque::new(),
+        })
+    }
+}
+
+impl Link for RemotePort {
+    fn recover(&mut self) -> Result<(), String> {
+        self.stream.write_all(&[FRAME_RECOVER]).map_err(|e| format!("Couldn't send recover request: {}", e))?;
+
+        let mut status = [0];
+        self.stream.read_exact(&mut status).map_err(|e| format!("Couldn't read recover status: {}", e))?;
+        if status[0] == STATUS_OK {
+            Ok(())
+        } else {
+            Err("Remote rig couldn't recover its link".into())
+        }
+    }
+}
+
+impl Read for RemotePort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut ret = 0;
+        for output_byte in buf.iter_mut() {
+            match self.response_buffer.pop_front() {
+                Some(b) => {
+                    *output_byte = b;
+                    ret += 1;
+                }
+                _ => break,
+            }
+        }
+        Ok(ret)
+    }
+}
+
+impl Write for RemotePort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // As with the emulated port, we assume that buf contains an entire packet (including its length byte).
+        let frame = [FRAME_EXCHANGE].iter()
+            .chain(buf.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+        self.stream.write_all(&frame)?;
+
+        let mut status = [0];
+        self.stream.read_exact(&mut status)?;
+        if status[0] != STATUS_OK {
+            return Err(io::Error::new(io::ErrorKind::Other, "Remote exchange failed"));
+        }
+
+        let mut received_packet_len = [0];
+        self.stream.read_exact(&mut received_packet_len)?;
+        let mut received_packet = vec![0; (received_packet_len[0] as usize) + 1];
+        self.stream.read_exact(&mut received_packet)?;
+
+        self.response_buffer.push_back(received_packet_len[0]);
+        self.response_buffer.extend(received_packet);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command;
+    use crapsum::Crapsum;
+
+    use std::thread;
+
+    // Answers check status and memory reads like the loader would, from a fake memory starting at 0. Anything else
+    //  fails the exchange, like a hung link would.
+    struct FakeLink {
+        mem: Vec<u8>,
+        read_data: Vec<u8>,
+        response: VecDeque<u8>,
+        failed_recoveries_left: usize,
+        recoveries: usize,
+    }
+
+    impl FakeLink {
+        fn new(failed_recoveries_left: usize) -> FakeLink {
+            FakeLink {
+                mem: (0..0x400).map(|i| i as u8).collect(),
+                read_data: Vec::new(),
+                response: VecDeque::new(),
+                failed_recoveries_left: failed_recoveries_left,
+                recoveries: 0,
+            }
+        }
+
+        fn respond(&mut self, packet: &[u8]) {
+            self.response.push_back((packet.len() - 1) as u8);
+            self.response.extend(packet.iter().cloned());
+        }
+
+        fn respond_ok(&mut self, packet: &[u8]) {
+            let state = Crapsum::compute(packet).state;
+            self.respond(&[0x01, state as u8, (state >> 8) as u8, (state >> 16) as u8, (state >> 24) as u8]);
+        }
+    }
+
+    impl Read for FakeLink {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut ret = 0;
+            for output_byte in buf.iter_mut() {
+                match self.response.pop_front() {
+                    Some(b) => {
+                        *output_byte = b;
+                        ret += 1;
+                    }
+                    _ => break,
+                }
+            }
+            Ok(ret)
+        }
+    }
+
+    impl Write for FakeLink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let packet = &buf[1..];
+            match packet[0] {
+                0x00 => self.respond_ok(packet),
+                0x02 => {
+                    let addr = (0..4).fold(0, |addr, i| addr | (packet[1 + i] as usize) << (i * 8));
+                    let len = (packet[5] as usize) + 1;
+                    self.read_data = self.mem[addr..addr + len].to_vec();
+                    self.respond_ok(packet);
+                }
+                0x03 => {
+                    let response = [0x02].iter().chain(self.read_data.iter()).cloned().collect::<Vec<_>>();
+                    self.respond(&response);
+                }
+                _ => return Err(io::Error::new(io::ErrorKind::TimedOut, "Fake link hung")),
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Link for FakeLink {
+        fn recover(&mut self) -> Result<(), String> {
+            self.recoveries += 1;
+            if self.failed_recoveries_left > 0 {
+                self.failed_recoveries_left -= 1;
+                return Err("Fake recovery failed".into());
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn commands_round_trip_through_bridge() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut link = FakeLink::new(1);
+            let (mut stream, _) = listener.accept().unwrap();
+            serve_client(&mut link, &mut stream).unwrap();
+            link
+        });
+
+        {
+            let mut port = RemotePort::connect(addr).unwrap();
+
+            command::check_status(&mut port).unwrap();
+            // Spans more than one read packet
+            let data = command::read_mem_region(&mut port, 0x10, 0x120).unwrap();
+            assert_eq!(data, (0x10..0x130).map(|i| i as u8).collect::<Vec<_>>());
+
+            // Exchanges the link fails come back as STATUS_FAILED, leaving the connection usable
+            match command::execute(&mut port, 0x05000400) {
+                Err(command::Error::Transport(_)) => (),
+                ret => panic!("Expected a transport error, got {:?}", ret),
+            }
+            assert!(port.recover().is_err());
+            port.recover().unwrap();
+            command::check_status(&mut port).unwrap();
+        }
+
+        // Hanging up ends the client's session cleanly
+        let link = server.join().unwrap();
+        assert_eq!(link.recoveries, 2);
+    }
+}
@@ -0,0 +1,400 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    Passed,
+    EmulatorMismatch,
+    HardwareNondeterministic,
+    TimedOut,
+    LinkError,
+}
+
+impl Outcome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            &Outcome::Passed => "passed",
+            &Outcome::EmulatorMismatch => "emulator-mismatch",
+            &Outcome::HardwareNondeterministic => "hardware-nondeterministic",
+            &Outcome::TimedOut => "timed-out",
+            &Outcome::LinkError => "link-error",
+        }
+    }
+}
+
+pub struct TestRecord {
+    pub name: String,
+    pub seed: usize,
+    // Value strategy the test ran with
+    pub values: String,
+    pub duration: Duration,
+    pub outcome: Outcome,
+    // The error, for anything that didn't pass
+    pub details: Option<String>,
+    // Where the failing case was written, if it was
+    pub artifacts: Option<String>,
+}
+
+// Structured results for scripts and dashboards:
+//  - a JSON lines stream with a record per test and one per suite iteration, appended as they finish
+//  - a JUnit XML file, rewritten with the latest suite iteration after each one finishes
+pub struct Reporter {
+    json: Option<File>,
+    junit_path: Option<String>,
+
+    records: Vec<TestRecord>,
+}
+
+impl Reporter {
+    pub fn new(json_path: Option<&str>, junit_path: Option<&str>) -> io::Result<Reporter> {
+        Ok(Reporter {
+            json: match json_path {
+                Some(json_path) => Some(File::create(json_path)?),
+                _ => None,
+            },
+            junit_path: junit_path.map(|junit_path| junit_path.into()),
+
+            records: Vec::new(),
+        })
+    }
+
+    pub fn record(&mut self, suite_iteration: usize, record: TestRecord) -> io::Result<()> {
+        if let Some(ref mut json) = self.json {
+            let mut fields = vec![
+                ("type", json_string("test")),
+                ("suite_iteration", suite_iteration.to_string()),
+                ("name", json_string(&record.name)),
+                ("seed", record.seed.to_string()),
+                ("values", json_string(&record.values)),
+                ("duration_ms", format!("{:.3}", millis(record.duration))),
+                ("result", json_string(record.outcome.name())),
+            ];
+            if let Some(ref details) = record.details {
+                fields.push(("details", json_string(details)));
+            }
+            if let Some(ref artifacts) = record.artifacts {
+                fields.push(("artifacts", json_string(artifacts)));
+            }
+            write_json_line(json, &fields)?;
+        }
+
+        self.records.push(record);
+        Ok(())
+    }
+
+    pub fn finish_suite(&mut self, suite_iteration: usize) -> io::Result<()> {
+        let duration = self.records.iter().fold(Duration::new(0, 0), |total, record| total + record.duration);
+
+        if let Some(ref mut json) = self.json {
+            let fields = vec![
+                ("type", json_string("suite")),
+                ("suite_iteration", suite_iteration.to_string()),
+                ("tests", self.records.len().to_string()),
+                ("passed", count(&self.records, Outcome::Passed).to_string()),
+                ("emulator_mismatches", count(&self.records, Outcome::EmulatorMismatch).to_string()),
+                ("hardware_nondeterministic", count(&self.records, Outcome::HardwareNondeterministic).to_string()),
+                ("timed_out", count(&self.records, Outcome::TimedOut).to_string()),
+                ("link_errors", count(&self.records, Outcome::LinkError).to_string()),
+                ("duration_ms", format!("{:.3}", millis(duration))),
+            ];
+            write_json_line(json, &fields)?;
+        }
+
+        if let Some(ref junit_path) = self.junit_path {
+            File::create(junit_path)?.write_all(junit(suite_iteration, &self.records, duration).as_bytes())?;
+        }
+
+        self.records.clear();
+        Ok(())
+    }
+}
+
+fn count(records: &[TestRecord], outcome: Outcome) -> usize {
+    records.iter().filter(|record| record.outcome == outcome).count()
+}
+
+fn millis(duration: Duration) -> f64 {
+    (duration.as_secs() as f64) * 1000.0 + (duration.subsec_nanos() as f64) / 1_000_000.0
+}
+
+fn seconds(duration: Duration) -> f64 {
+    millis(duration) / 1000.0
+}
+
+fn write_json_line(json: &mut File, fields: &[(&str, String)]) -> io::Result<()> {
+    let fields = fields.iter().map(|&(name, ref value)| format!("{}:{}", json_string(name), value)).collect::<Vec<_>>();
+    writeln!(json, "{{{}}}", fields.join(","))?;
+    // Keep the stream usable by anything tailing it
+    json.flush()
+}
+
+fn json_string(s: &str) -> String {
+    let mut ret = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => ret += "\\\"",
+            '\\' => ret += "\\\\",
+            '\n' => ret += "\\n",
+            '\r' => ret += "\\r",
+            '\t' => ret += "\\t",
+            c if (c as u32) < 0x20 => ret += &format!("\\u{:04x}", c as u32),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+// Emulator mismatches are failures. Timed out tests show up as skipped (there was nothing to compare), and
+//  nondeterministic hardware and link errors as errors, since neither says anything about the emulator.
+fn junit(suite_iteration: usize, records: &[TestRecord], duration: Duration) -> String {
+    let failures = count(records, Outcome::EmulatorMismatch);
+    let errors = count(records, Outcome::HardwareNondeterministic) + count(records, Outcome::LinkError);
+    let skipped = count(records, Outcome::TimedOut);
+
+    let mut ret = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    ret += &format!("<testsuites tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+        records.len(), failures, errors, skipped, seconds(duration));
+    ret += &format!("  <testsuite name=\"fuzzy iteration {}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+        suite_iteration, records.len(), failures, errors, skipped, seconds(duration));
+
+    for record in records.iter() {
+        ret += &format!("    <testcase classname=\"fuzzy\" name=\"{}\" time=\"{:.3}\">\n", xml_attr_escape(&record.name), seconds(record.duration));
+
+        let details = record.details.as_ref().map(|details| xml_escape(details)).unwrap_or_default();
+        let message = record.details.as_ref().map(|details| xml_attr_escape(details)).unwrap_or_default();
+        match record.outcome {
+            Outcome::Passed => (),
+            Outcome::EmulatorMismatch => ret += &format!("      <failure type=\"{}\" message=\"{}\">{}</failure>\n", record.outcome.name(), message, details),
+            Outcome::TimedOut => ret += &format!("      <skipped message=\"{}\"/>\n", message),
+            Outcome::HardwareNondeterministic | Outcome::LinkError => ret += &format!("      <error type=\"{}\" message=\"{}\">{}</error>\n", record.outcome.name(), message, details),
+        }
+
+        let mut out = format!("seed: {}\nvalues: {}\n", record.seed, record.values);
+        if let Some(ref artifacts) = record.artifacts {
+            out += &format!("artifacts: {}\n", artifacts);
+        }
+        ret += &format!("      <system-out>{}</system-out>\n", xml_escape(&out));
+
+        ret += "    </testcase>\n";
+    }
+
+    ret += "  </testsuite>\n";
+    ret += "</testsuites>\n";
+    ret
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut ret = String::new();
+    for c in s.chars() {
+        match c {
+            '&' => ret += "&amp;",
+            '<' => ret += "&lt;",
+            '>' => ret += "&gt;",
+            '"' => ret += "&quot;",
+            '\'' => ret += "&apos;",
+            // Not allowed in XML 1.0 at all
+            c if (c as u32) < 0x20 && c != '\n' && c != '\r' && c != '\t' => (),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+// Parsers turn raw line breaks in attributes into spaces, so multi-line details need them as character references
+fn xml_attr_escape(s: &str) -> String {
+    xml_escape(s).replace('\n', "&#10;").replace('\r', "&#13;").replace('\t', "&#9;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NASTY: &'static str = "targets disagree ([hw] vs [emu]):\n  r1: \"0x1\" <-> 'x' & \\ \t\r\u{1}\u{1f} ünïcode";
+
+    // Strict enough to catch anything a real JSON parser would reject in a string
+    fn json_unescape(s: &str) -> Option<String> {
+        if s.len() < 2 || !s.starts_with('"') || !s.ends_with('"') {
+            return None;
+        }
+
+        let mut ret = String::new();
+        let mut chars = s[1..s.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next()? {
+                    '"' => ret.push('"'),
+                    '\\' => ret.push('\\'),
+                    'n' => ret.push('\n'),
+                    'r' => ret.push('\r'),
+                    't' => ret.push('\t'),
+                    'u' => {
+                        let hex = (0..4).map(|_| chars.next()).collect::<Option<String>>()?;
+                        ret.push(::std::char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                    }
+                    _ => return None,
+                },
+                '"' => return None,
+                c if (c as u32) < 0x20 => return None,
+                c => ret.push(c),
+            }
+        }
+        Some(ret)
+    }
+
+    // Text or attribute values; raw quotes are rejected, since every value here ends up in an attribute somewhere
+    fn xml_unescape(s: &str) -> Option<String> {
+        let mut ret = String::new();
+        let mut rest = s;
+        while let Some(c) = rest.chars().next() {
+            if c == '&' {
+                let end = rest.find(';')?;
+                ret.push(match &rest[1..end] {
+                    "amp" => '&',
+                    "lt" => '<',
+                    "gt" => '>',
+                    "quot" => '"',
+                    "apos" => '\'',
+                    reference if reference.starts_with('#') => ::std::char::from_u32(reference[1..].parse().ok()?)?,
+                    _ => return None,
+                });
+                rest = &rest[end + 1..];
+            } else {
+                match c {
+                    '<' | '>' | '"' => return None,
+                    c if (c as u32) < 0x20 && c != '\n' && c != '\r' && c != '\t' => return None,
+                    c => ret.push(c),
+                }
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        Some(ret)
+    }
+
+    // Checks that tags nest and every attribute and bit of text is properly escaped, returning each element's name,
+    //  attributes and text
+    fn parse_xml(xml: &str) -> Option<Vec<(String, Vec<(String, String)>, String)>> {
+        let mut elements = Vec::new();
+        let mut open: Vec<usize> = Vec::new();
+        let mut rest = xml.trim_start().trim_start_matches(|c| c != '>').get(1..)?;
+        while let Some(start) = rest.find('<') {
+            let text = xml_unescape(&rest[..start])?;
+            if let Some(&index) = open.last() {
+                let element: &mut (String, Vec<(String, String)>, String) = &mut elements[index];
+                element.2 += &text;
+            }
+
+            let end = start + rest[start..].find('>')?;
+            let tag = &rest[start + 1..end];
+            rest = &rest[end + 1..];
+
+            if tag.starts_with('/') {
+                let index = open.pop()?;
+                if elements[index].0 != tag[1..] {
+                    return None;
+                }
+                continue;
+            }
+
+            let self_closing = tag.ends_with('/');
+            let tag = tag.trim_end_matches('/');
+            let name = tag.split_whitespace().next()?.to_string();
+            let mut attributes = Vec::new();
+            let mut attribute_rest = tag[name.len()..].trim();
+            while !attribute_rest.is_empty() {
+                let eq = attribute_rest.find("=\"")?;
+                let value_end = eq + 2 + attribute_rest[eq + 2..].find('"')?;
+                attributes.push((attribute_rest[..eq].to_string(), xml_unescape(&attribute_rest[eq + 2..value_end])?));
+                attribute_rest = attribute_rest[value_end + 1..].trim();
+            }
+
+            elements.push((name, attributes, String::new()));
+            if !self_closing {
+                open.push(elements.len() - 1);
+            }
+        }
+
+        if open.is_empty() && rest.trim().is_empty() {
+            Some(elements)
+        } else {
+            None
+        }
+    }
+
+    fn record(name: &str, outcome: Outcome, details: Option<&str>) -> TestRecord {
+        TestRecord {
+            name: name.into(),
+            seed: 7,
+            values: "uniform".into(),
+            duration: Duration::from_millis(1500),
+            outcome: outcome,
+            details: details.map(|details| details.into()),
+            artifacts: Some("failures/<odd & \"dir\">".into()),
+        }
+    }
+
+    #[test]
+    fn json_strings_round_trip() {
+        for s in ["", "plain", NASTY, "\u{0}\u{7f}\u{2028}"].iter() {
+            assert_eq!(json_unescape(&json_string(s)).as_ref().map(|x| x.as_str()), Some(*s));
+        }
+    }
+
+    #[test]
+    fn json_lines_stay_one_record_per_line() {
+        let path = ::std::env::temp_dir().join(format!("fuzzy-report-test-{}.json", ::std::process::id()));
+        {
+            let mut reporter = Reporter::new(Some(path.to_str().unwrap()), None).unwrap();
+            reporter.record(0, record(NASTY, Outcome::EmulatorMismatch, Some(NASTY))).unwrap();
+            reporter.record(0, record("ok", Outcome::Passed, None)).unwrap();
+            reporter.finish_suite(0).unwrap();
+        }
+        let mut json = String::new();
+        File::open(&path).and_then(|mut file| ::std::io::Read::read_to_string(&mut file, &mut json)).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        let lines = json.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        for line in lines.iter() {
+            assert!(line.starts_with('{') && line.ends_with('}'));
+        }
+        assert!(lines[0].contains(&format!("\"details\":{}", json_string(NASTY))));
+        assert!(lines[2].contains("\"type\":\"suite\"") && lines[2].contains("\"emulator_mismatches\":1"));
+    }
+
+    #[test]
+    fn xml_text_and_attributes_round_trip() {
+        assert_eq!(xml_unescape(&xml_escape("a < b && \"c\" > 'd'\n")), Some("a < b && \"c\" > 'd'\n".into()));
+        // Control characters other than whitespace can't be represented in XML 1.0 at all
+        assert_eq!(xml_unescape(&xml_escape(NASTY)), Some(NASTY.replace('\u{1}', "").replace('\u{1f}', "")));
+        let attr = xml_attr_escape(NASTY);
+        assert!(!attr.contains('\n') && !attr.contains('\r') && !attr.contains('\t'));
+        assert_eq!(xml_unescape(&attr), xml_unescape(&xml_escape(NASTY)));
+    }
+
+    #[test]
+    fn junit_is_well_formed() {
+        let records = vec![
+            record("passes", Outcome::Passed, None),
+            record(NASTY, Outcome::EmulatorMismatch, Some(NASTY)),
+            record("times out", Outcome::TimedOut, Some("timed out on all targets")),
+            record("flaky", Outcome::HardwareNondeterministic, Some(NASTY)),
+            record("link", Outcome::LinkError, Some("`hw` dispatch failed: ProtocolViolation")),
+        ];
+        let elements = parse_xml(&junit(3, &records, Duration::from_secs(2))).expect("JUnit output isn't well-formed");
+
+        let names = elements.iter().map(|&(ref name, _, _)| name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names.iter().filter(|&&name| name == "testcase").count(), records.len());
+        assert_eq!(names.iter().filter(|&&name| name == "failure").count(), 1);
+        assert_eq!(names.iter().filter(|&&name| name == "error").count(), 2);
+        assert_eq!(names.iter().filter(|&&name| name == "skipped").count(), 1);
+
+        let expected_details = NASTY.replace('\u{1}', "").replace('\u{1f}', "");
+        let failure = elements.iter().find(|&&(ref name, _, _)| name == "failure").unwrap();
+        assert_eq!(failure.2, expected_details);
+        assert!(failure.1.contains(&("message".into(), expected_details.clone())));
+        let testcase = elements.iter().find(|&&(ref name, ref attributes, _)| name == "testcase" && attributes.iter().any(|&(_, ref value)| value.starts_with("targets"))).unwrap();
+        assert!(testcase.1.contains(&("name".into(), expected_details)));
+    }
+}
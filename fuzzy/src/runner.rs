@@ -0,0 +1,959 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use artifacts;
+use command::{self, ExecuteResult};
+use coverage::Coverage;
+use generators::{build_rng, Mix, MIX_PRESETS};
+use guided::Guide;
+use memory;
+use mismatch;
+use placement;
+use report::{Outcome, Reporter, TestRecord};
+use suite;
+use target::Target;
+use trace::{self, TraceEntry};
+use values::ALL_VALUE_STRATEGIES;
+use vram;
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{stdout, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+// Needed to set up a TestContext
+pub use memory::MemRegion;
+pub use values::ValueStrategy;
+
+const DEFAULT_ARTIFACTS_DIR: &'static str = "failures";
+const MAX_RECOVERY_ATTEMPTS: usize = 3;
+
+#[derive(Debug)]
+pub enum TestError {
+    Dispatch { target: String, error: command::Error },
+    // Every target timed out, so there was nothing to compare
+    TimedOut,
+    // Targets are grouped by which ones agreed with each other
+    Mismatch { agreeing: Vec<Vec<String>>, details: String },
+    // Hardware runs (on different units, or repeated on one) disagreed with each other, so the case says nothing
+    //  about the emulator
+    Nondeterministic { agreeing: Vec<Vec<String>>, details: String },
+    BatchCase { index: usize, error: Box<TestError> },
+}
+
+impl fmt::Display for TestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &TestError::Dispatch { ref target, ref error } => write!(f, "`{}` dispatch failed: {:?}", target, error),
+            &TestError::TimedOut => write!(f, "timed out on all targets"),
+            &TestError::Mismatch { ref agreeing, ref details } => {
+                let groups = agreeing.iter().map(|group| format!("[{}]", group.join(", "))).collect::<Vec<_>>();
+                write!(f, "targets disagree ({}):\n{}", groups.join(" vs "), details)
+            }
+            &TestError::Nondeterministic { ref agreeing, ref details } => {
+                let groups = agreeing.iter().map(|group| format!("[{}]", group.join(", "))).collect::<Vec<_>>();
+                write!(f, "hardware runs disagree ({}):\n{}", groups.join(" vs "), details)
+            }
+            &TestError::BatchCase { index, ref error } => write!(f, "case {}: {}", index, error),
+        }
+    }
+}
+
+impl TestError {
+    // The error for the case that actually failed, looking through batches
+    pub fn case_error(&self) -> &TestError {
+        match self {
+            &TestError::BatchCase { ref error, .. } => error.case_error(),
+            _ => self,
+        }
+    }
+}
+
+pub struct TestContext<'a> {
+    pub targets: &'a mut [Box<Target>],
+    pub mix: &'a Mix,
+    // Strategy for initial regs and immediates
+    pub values: ValueStrategy,
+    // Fed with the rom of every test case and the coverage reference target's result
+    pub coverage: Option<&'a mut Coverage>,
+    // Carries what coverage-guided tests have learned from one test to the next
+    pub guide: Option<&'a mut Guide>,
+    // The case the targets disagreed on, so it can be re-run (eg. traced) after the test fails
+    pub failed_case: Option<TestCase>,
+    // Run every case on each hardware target a second time, so hardware that disagrees with itself isn't blamed on
+    //  the emulator
+    pub repeat_hardware: bool,
+    // Read back after every single-case run and compared across targets
+    pub mem_regions: &'a [MemRegion],
+    // Where single-case roms get loaded (see placement.rs); empty for the default rom addr
+    pub code_regions: &'a [MemRegion],
+}
+
+#[derive(Clone)]
+pub struct TestCase {
+    pub rom_addr: u32,
+    pub rom: Vec<u8>,
+    pub initial_regs: Vec<u32>,
+}
+
+impl TestCase {
+    pub fn new(rom: Vec<u8>, initial_regs: Vec<u32>) -> TestCase {
+        TestCase::at(placement::DEFAULT_ROM_ADDR, rom, initial_regs)
+    }
+
+    pub fn at(rom_addr: u32, rom: Vec<u8>, initial_regs: Vec<u32>) -> TestCase {
+        TestCase {
+            rom_addr: rom_addr,
+            rom: rom,
+            initial_regs: initial_regs,
+        }
+    }
+}
+
+pub fn test_rom(ctx: &mut TestContext, rom: &[u8], initial_regs: &[u32]) -> Result<(), TestError> {
+    test_rom_with_known_result(ctx, rom, initial_regs, None)
+}
+
+// Like test_rom, but also compares elapsed ticks between the targets that measure them, allowing for `tolerance`
+//  ticks either way. Only worth it for roms that run long enough for cycle differences to add up to whole ticks.
+pub fn test_rom_timed(ctx: &mut TestContext, rom: &[u8], initial_regs: &[u32], tolerance: u32) -> Result<(), TestError> {
+    run_and_compare(ctx, rom, initial_regs, None, Some(tolerance))
+}
+
+// Like test_rom, but reuses a result we already have for one of the targets instead of running the rom there again
+pub fn test_rom_with_known_result(ctx: &mut TestContext, rom: &[u8], initial_regs: &[u32], known_result: Option<(&str, &ExecuteResult)>) -> Result<(), TestError> {
+    run_and_compare(ctx, rom, initial_regs, known_result, None)
+}
+
+fn run_and_compare(ctx: &mut TestContext, rom: &[u8], initial_regs: &[u32], known_result: Option<(&str, &ExecuteResult)>, timing_tolerance: Option<u32>) -> Result<(), TestError> {
+    let mem_regions = ctx.mem_regions;
+    let rom_addr = placement::pick_rom_addr(ctx.code_regions, rom, initial_regs);
+    let targets = &mut *ctx.targets;
+
+    /*{
+        use std::fs::File;
+        let mut file = File::create("derp.vxe").unwrap();
+        file.write_all(&rom).unwrap();
+    }*/
+
+    // Kick off the targets that can run in the background first, so they overlap with the ones that can't
+    let mut results = vec![None; targets.len()];
+    for (index, target) in targets.iter_mut().enumerate() {
+        match known_result {
+            Some((name, result)) if name == target.name() => results[index] = Some(result.clone()),
+            _ if target.capabilities().background => {
+                prepare(target, mem_regions).and_then(|_| target.start_run_test(rom_addr, rom, initial_regs)).map_err(|e| TestError::Dispatch { target: target.name().into(), error: e })?;
+            }
+            _ => (),
+        }
+    }
+    for (index, target) in targets.iter_mut().enumerate() {
+        if results[index].is_none() && !target.capabilities().background {
+            results[index] = Some(prepare(target, mem_regions).and_then(|_| target.run_test(rom_addr, rom, initial_regs)).map_err(|e| TestError::Dispatch { target: target.name().into(), error: e })?);
+        }
+    }
+    for (index, target) in targets.iter_mut().enumerate() {
+        if results[index].is_none() {
+            results[index] = Some(target.finish_run_test().map_err(|e| TestError::Dispatch { target: target.name().into(), error: e })?);
+        }
+    }
+    let mut results = results.into_iter().map(|result| result.unwrap()).collect::<Vec<_>>();
+
+    // Before any repeats overwrite it. The target with a known result didn't just run this rom, and targets that
+    //  can't read memory back have nothing to offer.
+    let mut mem_names = Vec::new();
+    let mut mem_captures = Vec::new();
+    let mut mem_hardware = Vec::new();
+    if !mem_regions.is_empty() {
+        for target in targets.iter_mut() {
+            let is_known = known_result.map(|(name, _)| name == target.name()).unwrap_or(false);
+            if is_known || !target.capabilities().read_mem {
+                continue;
+            }
+            mem_captures.push(memory::capture(target, mem_regions).map_err(|e| TestError::Dispatch { target: target.name().into(), error: e })?);
+            mem_names.push(target.name().to_string());
+            mem_hardware.push(target.capabilities().hardware);
+        }
+    }
+
+    let mut names = target_names(targets);
+    let mut hardware = targets.iter().map(|target| target.capabilities().hardware).collect::<Vec<_>>();
+    let mut timing = targets.iter().map(|target| target.capabilities().timing).collect::<Vec<_>>();
+
+    if ctx.repeat_hardware {
+        for target in targets.iter_mut().filter(|target| target.capabilities().hardware) {
+            let result = target.reset().and_then(|_| target.run_test(rom_addr, rom, initial_regs)).map_err(|e| TestError::Dispatch { target: target.name().into(), error: e })?;
+            names.push(repeat_name(target.name()));
+            results.push(result);
+            hardware.push(true);
+            timing.push(target.capabilities().timing);
+        }
+    }
+
+    if let Some(ref mut coverage) = ctx.coverage {
+        record_coverage(coverage, rom, &names, &results);
+    }
+
+    let ret = compare_hardware(&names, &results, &hardware)
+        .and_then(|_| compare_results(&names, &results))
+        .and_then(|_| compare_memory(&mem_names, &mem_captures, &mem_hardware, mem_regions))
+        .and_then(|_| match timing_tolerance {
+            Some(tolerance) => compare_timing(&names, &results, &hardware, &timing, tolerance),
+            _ => Ok(()),
+        });
+    match ret {
+        Err(TestError::Mismatch { .. }) | Err(TestError::Nondeterministic { .. }) => ctx.failed_case = Some(TestCase::at(rom_addr, rom.to_vec(), initial_regs.to_vec())),
+        _ => (),
+    }
+    ret
+}
+
+// Captured memory has to start out the same on every target, so it's filled in after the reset
+fn prepare(target: &mut Box<Target>, mem_regions: &[MemRegion]) -> Result<(), command::Error> {
+    target.reset()?;
+    if target.capabilities().read_mem {
+        memory::fill(target, mem_regions)?;
+    }
+    Ok(())
+}
+
+// Runs a rom on a single target, eg. to screen it on the emulator before spending hardware time on it.
+//  Returns None if there's no target with that name. Doesn't count towards coverage, since nothing gets compared.
+//  The rom goes wherever test_rom would put it, so the result can be reused there.
+pub fn run_on_target(ctx: &mut TestContext, name: &str, rom: &[u8], initial_regs: &[u32]) -> Result<Option<ExecuteResult>, TestError> {
+    let rom_addr = placement::pick_rom_addr(ctx.code_regions, rom, initial_regs);
+
+    match ctx.targets.iter_mut().find(|target| target.name() == name) {
+        Some(target) => target.reset().and_then(|_| target.run_test(rom_addr, rom, initial_regs))
+            .map(Some)
+            .map_err(|e| TestError::Dispatch { target: name.into(), error: e }),
+        _ => Ok(None),
+    }
+}
+
+// Re-runs a case on one target with instruction tracing. Returns None if the target can't trace.
+pub fn trace_case(target: &mut Box<Target>, case: &TestCase) -> Result<Option<(ExecuteResult, Vec<TraceEntry>)>, command::Error> {
+    target.reset()?;
+    target.trace_test(case.rom_addr, &case.rom, &case.initial_regs)
+}
+
+pub fn test_rom_batch(ctx: &mut TestContext, cases: &[TestCase]) -> Result<(), TestError> {
+    let targets = &mut *ctx.targets;
+
+    // Batches are packed into WRAM from the default rom addr (see test_rom_batch_on_target), so code regions don't
+    //  apply to them
+    let batch_addr = placement::DEFAULT_ROM_ADDR;
+
+    // results[target][case]
+    let mut results = Vec::new();
+    for target in targets.iter_mut() {
+        let target_results = if target.capabilities().batch {
+            target.reset().and_then(|_| test_rom_batch_on_target(target, cases, batch_addr))
+        } else {
+            cases.iter().map(|case| target.reset().and_then(|_| target.run_test(batch_addr, &case.rom, &case.initial_regs))).collect()
+        };
+        results.push(target_results.map_err(|e| TestError::Dispatch { target: target.name().into(), error: e })?);
+    }
+
+    let mut names = target_names(targets);
+    let mut hardware = targets.iter().map(|target| target.capabilities().hardware).collect::<Vec<_>>();
+
+    if ctx.repeat_hardware {
+        for target in targets.iter_mut().filter(|target| target.capabilities().hardware) {
+            let target_results = if target.capabilities().batch {
+                target.reset().and_then(|_| test_rom_batch_on_target(target, cases, batch_addr))
+            } else {
+                cases.iter().map(|case| target.reset().and_then(|_| target.run_test(batch_addr, &case.rom, &case.initial_regs))).collect()
+            };
+            results.push(target_results.map_err(|e| TestError::Dispatch { target: target.name().into(), error: e })?);
+            names.push(repeat_name(target.name()));
+            hardware.push(true);
+        }
+    }
+
+    // Report the first case that actually disagrees; cases where everything timed out are inconclusive,
+    //  so they only count against the batch if nothing else went wrong
+    let mut any_timed_out = false;
+    for index in 0..cases.len() {
+        let case_results = results.iter().map(|target_results| target_results[index].clone()).collect::<Vec<_>>();
+        if let Some(ref mut coverage) = ctx.coverage {
+            record_coverage(coverage, &cases[index].rom, &names, &case_results);
+        }
+        match compare_hardware(&names, &case_results, &hardware).and_then(|_| compare_results(&names, &case_results)) {
+            Ok(()) => (),
+            Err(TestError::TimedOut) => any_timed_out = true,
+            Err(e) => {
+                ctx.failed_case = Some(cases[index].clone());
+                return Err(TestError::BatchCase { index: index, error: Box::new(e) });
+            }
+        }
+    }
+
+    if any_timed_out {
+        Err(TestError::TimedOut)
+    } else {
+        Ok(())
+    }
+}
+
+pub fn compare_results(names: &[String], results: &[ExecuteResult]) -> Result<(), TestError> {
+    // Group targets that agree with each other. Timing is deliberately left out of this.
+    let mut groups: Vec<(&ExecuteResult, Vec<usize>)> = Vec::new();
+    for (index, result) in results.iter().enumerate() {
+        match groups.iter().position(|&(group_result, _)| results_agree(group_result, result)) {
+            Some(group_index) => groups[group_index].1.push(index),
+            None => groups.push((result, vec![index])),
+        }
+    }
+
+    if groups.len() <= 1 {
+        return match results.first() {
+            Some(&ExecuteResult::TimedOut) => Err(TestError::TimedOut),
+            _ => Ok(()),
+        };
+    }
+
+    let agreeing = groups.iter().map(|&(_, ref indices)| indices.iter().map(|&index| names[index].clone()).collect()).collect();
+
+    let details = mismatch::describe(names, results);
+
+    Err(TestError::Mismatch { agreeing: agreeing, details: details })
+}
+
+// Like the regs, memory that differs between hardware units is Nondeterministic rather than a Mismatch
+fn compare_memory(names: &[String], captures: &[Vec<Vec<u8>>], hardware: &[bool], regions: &[MemRegion]) -> Result<(), TestError> {
+    let hardware_names = names.iter().zip(hardware.iter()).filter(|&(_, &hardware)| hardware).map(|(name, _)| name.clone()).collect::<Vec<_>>();
+    let hardware_captures = captures.iter().zip(hardware.iter()).filter(|&(_, &hardware)| hardware).map(|(capture, _)| capture.clone()).collect::<Vec<_>>();
+    if let Some((agreeing, details)) = memory::diff(&hardware_names, &hardware_captures, regions) {
+        return Err(TestError::Nondeterministic { agreeing: agreeing, details: details });
+    }
+
+    match memory::diff(names, captures, regions) {
+        Some((agreeing, details)) => Err(TestError::Mismatch { agreeing: agreeing, details: details }),
+        _ => Ok(()),
+    }
+}
+
+// Elapsed ticks are judged against the range the hardware runs cover (give or take the tolerance), since a run can
+//  start anywhere within a tick. Hardware runs further apart than that are Nondeterministic.
+fn compare_timing(names: &[String], results: &[ExecuteResult], hardware: &[bool], timing: &[bool], tolerance: u32) -> Result<(), TestError> {
+    let mut ticks = Vec::new();
+    for (index, result) in results.iter().enumerate() {
+        match result {
+            &ExecuteResult::Completed { elapsed_ticks, .. } if timing[index] => ticks.push((index, elapsed_ticks)),
+            _ => (),
+        }
+    }
+
+    let hardware_ticks = ticks.iter().filter(|&&(index, _)| hardware[index]).map(|&(_, elapsed_ticks)| elapsed_ticks).collect::<Vec<_>>();
+    let (min, max) = match (hardware_ticks.iter().min(), hardware_ticks.iter().max()) {
+        (Some(&min), Some(&max)) => (min, max),
+        // Nothing to judge against
+        _ => return Ok(()),
+    };
+
+    let details = format!("elapsed ticks (20us, tolerance {}): {}",
+        tolerance,
+        ticks.iter().map(|&(index, elapsed_ticks)| format!("{} {}", names[index], elapsed_ticks)).collect::<Vec<_>>().join(" | "));
+
+    if max - min > tolerance {
+        let agreeing = ticks.iter().filter(|&&(index, _)| hardware[index]).map(|&(index, _)| vec![names[index].clone()]).collect();
+        return Err(TestError::Nondeterministic { agreeing: agreeing, details: details });
+    }
+
+    let (within, outside): (Vec<_>, Vec<_>) = ticks.iter().partition(|&&(_, elapsed_ticks)| elapsed_ticks + tolerance >= max && elapsed_ticks <= min + tolerance);
+    if outside.is_empty() {
+        return Ok(());
+    }
+
+    let agreeing = vec![
+        within.iter().map(|&&(index, _)| names[index].clone()).collect(),
+        outside.iter().map(|&&(index, _)| names[index].clone()).collect(),
+    ];
+    Err(TestError::Mismatch { agreeing: agreeing, details: details })
+}
+
+// Only compares the hardware results, turning any disagreement into Nondeterministic
+fn compare_hardware(names: &[String], results: &[ExecuteResult], hardware: &[bool]) -> Result<(), TestError> {
+    let hardware_names = names.iter().zip(hardware.iter()).filter(|&(_, &hardware)| hardware).map(|(name, _)| name.clone()).collect::<Vec<_>>();
+    let hardware_results = results.iter().zip(hardware.iter()).filter(|&(_, &hardware)| hardware).map(|(result, _)| result.clone()).collect::<Vec<_>>();
+
+    match compare_results(&hardware_names, &hardware_results) {
+        Err(TestError::Mismatch { agreeing, details }) => Err(TestError::Nondeterministic { agreeing: agreeing, details: details }),
+        // Hardware that consistently times out is for the full comparison to judge
+        _ => Ok(()),
+    }
+}
+
+fn repeat_name(name: &str) -> String {
+    format!("{} (repeat)", name)
+}
+
+fn results_agree(a: &ExecuteResult, b: &ExecuteResult) -> bool {
+    match (a, b) {
+        (&ExecuteResult::Completed { regs: ref a_regs, .. }, &ExecuteResult::Completed { regs: ref b_regs, .. }) => a_regs == b_regs,
+        (&ExecuteResult::TimedOut, &ExecuteResult::TimedOut) => true,
+        _ => false,
+    }
+}
+
+fn record_coverage(coverage: &mut Coverage, rom: &[u8], names: &[String], results: &[ExecuteResult]) {
+    let reference_result = names.iter().position(|name| name == coverage.reference_target()).map(|index| &results[index]);
+    coverage.record(rom, reference_result);
+}
+
+fn target_names(targets: &[Box<Target>]) -> Vec<String> {
+    targets.iter().map(|target| target.name().to_string()).collect()
+}
+
+pub fn test_rom_batch_on_target(target: &mut Box<Target>, cases: &[TestCase], batch_addr: u32) -> Result<Vec<ExecuteResult>, command::Error> {
+    // Batch layout (see loader's execute batch command):
+    //  table: num cases, then per case { entry, initial regs, result words }
+    //  roms: packed back to back after the table, each word-aligned
+    // Everything has to fit below the loader's stack at the top of WRAM.
+    let batch_end_limit = 0x05000000 + 0xe000;
+
+    let case_record_len = 4 + (command::NUM_INITIAL_REGS + command::NUM_RESULT_WORDS) * 4;
+    let table_len = 4 + cases.len() * case_record_len;
+
+    let mut table = Vec::new();
+    let mut roms = Vec::new();
+    table.write_u32::<LittleEndian>(cases.len() as u32).unwrap();
+    for case in cases.iter() {
+        if case.initial_regs.len() != command::NUM_INITIAL_REGS {
+            return Err(command::Error::WrongNumInitialRegs);
+        }
+
+        let entry = batch_addr + (table_len + roms.len()) as u32;
+        roms.extend(&case.rom);
+        while roms.len() % 4 != 0 {
+            roms.push(0);
+        }
+
+        table.write_u32::<LittleEndian>(entry).unwrap();
+        for reg in case.initial_regs.iter() {
+            table.write_u32::<LittleEndian>(*reg).unwrap();
+        }
+        for _ in 0..command::NUM_RESULT_WORDS {
+            table.write_u32::<LittleEndian>(0).unwrap();
+        }
+    }
+
+    if batch_addr + (table.len() + roms.len()) as u32 > batch_end_limit {
+        return Err(command::Error::DataTooLarge);
+    }
+
+    table.extend(roms);
+    target.upload(batch_addr, &table)?;
+
+    target.execute_batch(batch_addr, cases.len())?;
+
+    // Read back the whole table in one pass, then pick out each case's result words
+    let mut tries = 0;
+    let table_bytes = loop {
+        match target.read_mem(batch_addr, table_len as u32) {
+            Ok(table_bytes) => break table_bytes,
+            Err(e) => {
+                tries += 1;
+                if tries >= 200 {
+                    return Err(e);
+                }
+            }
+        }
+    };
+
+    let mut results = Vec::new();
+    for record in table_bytes[4..].chunks(case_record_len) {
+        let mut result_bytes = &record[4 + command::NUM_INITIAL_REGS * 4..];
+        let result_words = (0..command::NUM_RESULT_WORDS).map(|_| result_bytes.read_u32::<LittleEndian>().unwrap()).collect::<Vec<_>>();
+        results.push(ExecuteResult::from_result_words(result_words)?);
+    }
+
+    Ok(results)
+}
+
+// What a test run (see run) does besides running the suite
+pub struct RunOptions {
+    mix: Mix,
+    // Picked per test when not forced
+    values: Option<ValueStrategy>,
+    coverage_report: Option<String>,
+    json_report: Option<String>,
+    junit_report: Option<String>,
+    artifacts_dir: String,
+    repeat_hardware: bool,
+    mem_regions: Vec<MemRegion>,
+    code_regions: Vec<MemRegion>,
+    render_seed: Option<usize>,
+}
+
+impl RunOptions {
+    // Takes the run options (see the usage in main.rs) out of args, wherever they are, leaving everything else
+    pub fn from_args(args: &mut Vec<String>) -> Result<RunOptions, String> {
+        Ok(RunOptions {
+            mix: match take_option(args, "--mix")? {
+                Some(mix_arg) => load_mix(&mix_arg).map_err(|e| format!("Couldn't load mix `{}`: {}", mix_arg, e))?,
+                _ => Mix::uniform(),
+            },
+            values: match take_option(args, "--values")? {
+                Some(values_arg) => Some(ValueStrategy::from_name(&values_arg).ok_or_else(|| {
+                    let names = ALL_VALUE_STRATEGIES.iter().map(|strategy| strategy.name()).collect::<Vec<_>>();
+                    format!("Unknown value strategy `{}` (expected one of {})", values_arg, names.join(", "))
+                })?),
+                _ => None,
+            },
+            coverage_report: take_option(args, "--coverage")?,
+            json_report: take_option(args, "--json")?,
+            junit_report: take_option(args, "--junit")?,
+            artifacts_dir: take_option(args, "--artifacts")?.unwrap_or_else(|| DEFAULT_ARTIFACTS_DIR.into()),
+            repeat_hardware: take_flag(args, "--repeat-hw"),
+            mem_regions: match take_option(args, "--capture-mem")? {
+                Some(regions_arg) => memory::parse_regions(&regions_arg).map_err(|e| format!("Invalid memory regions `{}`: {}", regions_arg, e))?,
+                _ => Vec::new(),
+            },
+            code_regions: match take_option(args, "--code-regions")? {
+                Some(regions_arg) => placement::parse_code_regions(&regions_arg).map_err(|e| format!("Invalid code regions `{}`: {}", regions_arg, e))?,
+                _ => Vec::new(),
+            },
+            render_seed: match take_option(args, "--render")? {
+                Some(seed_arg) => Some(seed_arg.parse().map_err(|e| format!("Invalid seed `{}`: {}", seed_arg, e))?),
+                _ => None,
+            },
+        })
+    }
+}
+
+// Runs the suite over and over until something fails for real, or renders a scene if that's what the options ask
+//  for. Scenes are diffed against the first target's, so that should be the hardware.
+pub fn run(targets: Vec<Box<Target>>, options: &RunOptions) {
+    match options.render_seed {
+        Some(seed) => render_scene(targets, seed, options),
+        _ => run_tests(targets, options),
+    }
+}
+
+// Images of each target's framebuffers and chars go in <artifacts dir>/render-<seed>/, along with diffs against the
+//  first target's
+fn render_scene(mut targets: Vec<Box<Target>>, seed: usize, options: &RunOptions) {
+    let case = suite::vram_scene_case(seed, ValueStrategy::Uniform);
+
+    print!("Rendering scene {} ... ", seed);
+    stdout().flush().unwrap();
+    let result = {
+        let mut ctx = TestContext {
+            targets: &mut targets,
+            mix: &options.mix,
+            values: ValueStrategy::Uniform,
+            coverage: None,
+            guide: None,
+            failed_case: None,
+            repeat_hardware: false,
+            mem_regions: &[],
+            code_regions: &[],
+        };
+        test_rom(&mut ctx, &case.rom, &case.initial_regs)
+    };
+    match result {
+        Ok(()) => println!("ok"),
+        // Regs are secondary here; the images are still worth a look
+        Err(e) => println!("ERROR: {}", e),
+    }
+
+    let dir = Path::new(&options.artifacts_dir).join(format!("render-{}", seed));
+    fs::create_dir_all(&dir).expect("Couldn't create render dir");
+
+    // Targets whose VRAM can't be read back are left out of the comparison
+    let mut captures = Vec::new();
+    for target in targets.iter_mut() {
+        let vram = vram::capture(target);
+        vram::resume_drawing(target).unwrap_or_else(|e| println!("Couldn't resume drawing on `{}`: {:?}", target.name(), e));
+        match vram {
+            Ok(vram) => {
+                vram::write_images(&dir, target.name(), &vram).expect("Couldn't write images");
+                captures.push((target.name().to_string(), vram));
+            }
+            Err(e) => println!("Couldn't read back `{}` VRAM: {:?}", target.name(), e),
+        }
+    }
+
+    let (&(ref reference_name, ref reference), others) = match captures.split_first() {
+        Some(split) => split,
+        _ => {
+            println!("No images to write");
+            return;
+        }
+    };
+    for &(ref name, ref vram) in others.iter() {
+        let diffs = vram::write_diffs(&dir, name, reference, vram).expect("Couldn't write image diffs");
+        let diffs = diffs.iter().map(|&(image, num_differing)| format!("{} {}", image, num_differing)).collect::<Vec<_>>();
+        println!("`{}` vs `{}` differing pixels: {}", name, reference_name, diffs.join(", "));
+    }
+
+    println!("Images written to {}", dir.display());
+}
+
+fn run_tests(mut targets: Vec<Box<Target>>, options: &RunOptions) {
+    // Flag outcomes come from the emulator, since it's always there and always agrees with itself
+    let mut coverage = options.coverage_report.as_ref().map(|_| Coverage::new("emu"));
+    // Guided tests screen candidates on the emulator and only send novel ones to the hardware
+    let mut guide = Guide::new("emu");
+
+    let mut reporter = Reporter::new(options.json_report.as_ref().map(|x| x.as_str()), options.junit_report.as_ref().map(|x| x.as_str()))
+        .expect("Couldn't create test reports");
+
+    let tests = suite::all_tests();
+
+    let mut suite_iteration = 0;
+
+    // Hardware and link flakiness only shows up in the long run
+    let mut total_tests = 0;
+    let mut total_nondeterministic_tests = 0;
+    let mut total_link_error_tests = 0;
+
+    loop {
+        println!("Suite iteration: {}", suite_iteration);
+
+        let num_tests = tests.len();
+        let mut passed_tests = 0;
+        let mut mismatch_tests = 0;
+        let mut nondeterministic_tests = 0;
+        let mut timed_out_tests = 0;
+        let mut link_error_tests = 0;
+        // Set by anything that should end the campaign
+        let mut failed = false;
+
+        for (index, &(ref test_fn, test_name)) in tests.iter().enumerate() {
+            let seed = suite_iteration + index;
+            let values = options.values.unwrap_or_else(|| ValueStrategy::choose(&mut build_rng(seed)));
+
+            print!("({}) running test `{}` (values: {}) ... ", index, test_name, values.name());
+            stdout().flush().unwrap();
+            let mut ctx = TestContext {
+                targets: &mut targets,
+                mix: &options.mix,
+                values: values,
+                coverage: coverage.as_mut(),
+                guide: Some(&mut guide),
+                failed_case: None,
+                repeat_hardware: options.repeat_hardware,
+                mem_regions: &options.mem_regions,
+                code_regions: &options.code_regions,
+            };
+            let start = Instant::now();
+            let result = test_fn(&mut ctx, seed);
+            let duration = start.elapsed();
+            let failed_case = ctx.failed_case.take();
+            let (outcome, artifacts) = match result {
+                Ok(()) => {
+                    println!("ok");
+                    passed_tests += 1;
+                    (Outcome::Passed, None)
+                }
+                Err(TestError::TimedOut) => {
+                    // Every target hung, so there's nothing to compare; not worth stopping a campaign over
+                    println!("timed out");
+                    timed_out_tests += 1;
+                    (Outcome::TimedOut, None)
+                }
+                Err(TestError::Dispatch { ref target, ref error }) => {
+                    println!("ERROR: `{}` dispatch failed: {:?}", target, error);
+                    link_error_tests += 1;
+
+                    match targets.iter_mut().find(|t| t.name() == target && t.capabilities().recover) {
+                        Some(failed_target) => {
+                            // Failed attempts count as link errors too. Losing the link for good ends the campaign,
+                            //  but cleanly, with the reports written.
+                            let (recovered, failed_attempts) = recover_target(failed_target);
+                            link_error_tests += failed_attempts;
+                            if !recovered {
+                                failed = true;
+                            }
+                        }
+                        // Nothing more we can do with this target
+                        _ => failed = true,
+                    }
+                    (Outcome::LinkError, None)
+                }
+                Err(ref e) => {
+                    println!("ERROR: {}", e);
+
+                    // Hardware that can't agree with itself isn't worth stopping for, but the case is still worth keeping
+                    let outcome = match e.case_error() {
+                        &TestError::Nondeterministic { .. } => {
+                            nondeterministic_tests += 1;
+                            Outcome::HardwareNondeterministic
+                        }
+                        _ => {
+                            mismatch_tests += 1;
+                            failed = true;
+                            Outcome::EmulatorMismatch
+                        }
+                    };
+
+                    let artifacts = match failed_case {
+                        Some(ref case) => save_failure(&mut targets, &options.artifacts_dir, test_name, seed, case, e),
+                        _ => None,
+                    };
+                    (outcome, artifacts)
+                }
+            };
+
+            reporter.record(suite_iteration, TestRecord {
+                name: test_name.into(),
+                seed: seed,
+                values: values.name().into(),
+                duration: duration,
+                outcome: outcome,
+                details: result.err().map(|e| e.to_string()),
+                artifacts: artifacts.map(|artifacts| artifacts.display().to_string()),
+            }).unwrap_or_else(|e| println!("Couldn't write test report: {}", e));
+        }
+
+        reporter.finish_suite(suite_iteration).unwrap_or_else(|e| println!("Couldn't write suite report: {}", e));
+
+        println!("");
+        println!("Ran {} tests, {} passed, {} emulator mismatches, {} hardware nondeterministic, {} timed out, {} link errors",
+            num_tests, passed_tests, mismatch_tests, nondeterministic_tests, timed_out_tests, link_error_tests);
+
+        total_tests += num_tests;
+        total_nondeterministic_tests += nondeterministic_tests;
+        total_link_error_tests += link_error_tests;
+        println!("Over the whole run: {} tests, {} hardware nondeterministic ({:.2}%), {} link errors ({:.2}%)",
+            total_tests,
+            total_nondeterministic_tests, percentage(total_nondeterministic_tests, total_tests),
+            total_link_error_tests, percentage(total_link_error_tests, total_tests));
+
+        println!("{}", guide.summary());
+
+        if let (Some(ref coverage), Some(ref report_path)) = (coverage.as_ref(), options.coverage_report.as_ref()) {
+            println!("{}", coverage.summary());
+            File::create(report_path)
+                .and_then(|mut file| file.write_all(coverage.report().as_bytes()))
+                .unwrap_or_else(|e| println!("Couldn't write coverage report: {}", e));
+        }
+
+        if failed {
+            println!("FAILED ON SUITE ITERATION {}", suite_iteration);
+            break;
+        }
+
+        println!("");
+
+        suite_iteration += 1;
+    }
+}
+
+// Gives recovery a few tries, returning whether it worked and how many attempts failed
+fn recover_target(target: &mut Box<Target>) -> (bool, usize) {
+    let mut failed_attempts = 0;
+    while failed_attempts < MAX_RECOVERY_ATTEMPTS {
+        print!("    recovering `{}` link ... ", target.name());
+        stdout().flush().unwrap();
+        match target.recover() {
+            Ok(()) => {
+                println!("ok");
+                return (true, failed_attempts);
+            }
+            Err(e) => {
+                println!("ERROR: {}", e);
+                failed_attempts += 1;
+            }
+        }
+    }
+
+    println!("    giving up on `{}` link", target.name());
+    (false, failed_attempts)
+}
+
+fn percentage(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        (count as f64) * 100.0 / (total as f64)
+    }
+}
+
+// Writes the failing case out, and re-runs it with tracing wherever that's possible (normally just the emulator)
+fn save_failure(targets: &mut [Box<Target>], artifacts_dir: &str, test_name: &str, seed: usize, case: &TestCase, error: &TestError) -> Option<PathBuf> {
+    let case_dir = match artifacts::write_failure(artifacts_dir, test_name, seed, case, error) {
+        Ok(case_dir) => case_dir,
+        Err(e) => {
+            println!("    couldn't write failure artifacts: {}", e);
+            return None;
+        }
+    };
+    println!("    failing case written to {}", case_dir.display());
+
+    for target in targets.iter_mut() {
+        match trace_case(target, case) {
+            Ok(Some((_, entries))) => {
+                let trace = trace::format(&entries, &case.rom, case.rom_addr);
+                match artifacts::write_trace(&case_dir, target.name(), &trace) {
+                    Ok(()) => println!("    `{}` traced {} instructions", target.name(), entries.len()),
+                    Err(e) => println!("    couldn't write `{}` trace: {}", target.name(), e),
+                }
+            }
+            Ok(None) => (),
+            Err(e) => println!("    couldn't trace on `{}`: {:?}", target.name(), e),
+        }
+    }
+
+    Some(case_dir)
+}
+
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|arg| arg == name) {
+        Some(index) => {
+            args.remove(index);
+            true
+        }
+        _ => false,
+    }
+}
+
+// Takes `name` and the value after it out of args, wherever they are
+pub fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    match args.iter().position(|arg| arg == name) {
+        Some(index) => {
+            let value = args.get(index + 1).cloned().ok_or_else(|| format!("Missing value for `{}`", name))?;
+            args.drain(index..index + 2);
+            Ok(Some(value))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn load_mix(mix_arg: &str) -> Result<Mix, String> {
+    match Mix::preset(mix_arg) {
+        Some(mix) => Ok(mix),
+        _ if Path::new(mix_arg).exists() => Mix::load(mix_arg),
+        _ => Err(format!("Not a mix file or one of the presets ({})", MIX_PRESETS.join(", "))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use target::{Capabilities, MockTarget};
+
+    const EMU: Capabilities = Capabilities {
+        batch: false,
+        read_mem: false,
+        timing: false,
+        recover: false,
+        background: false,
+        hardware: false,
+    };
+    const HW: Capabilities = Capabilities {
+        hardware: true,
+        ..EMU
+    };
+
+    // r1 = first rom byte + offset, so targets can be made to disagree about some roms but not others
+    fn completed(rom: &[u8], offset: u32) -> ExecuteResult {
+        let mut regs = vec![0; 33];
+        regs[1] = rom[0] as u32 + offset;
+        ExecuteResult::Completed { regs: regs, elapsed_ticks: 0 }
+    }
+
+    fn mock(name: &str, offset: u32, capabilities: Capabilities) -> Box<Target> {
+        Box::new(MockTarget::new(name, move |rom: &[u8], _: &[u32]| completed(rom, offset), capabilities))
+    }
+
+    fn run<F: FnOnce(&mut TestContext) -> Result<(), TestError>>(targets: &mut [Box<Target>], f: F) -> (Result<(), TestError>, Option<TestCase>) {
+        run_capturing(targets, &[], f)
+    }
+
+    fn run_capturing<F: FnOnce(&mut TestContext) -> Result<(), TestError>>(targets: &mut [Box<Target>], mem_regions: &[MemRegion], f: F) -> (Result<(), TestError>, Option<TestCase>) {
+        let mix = Mix::uniform();
+        let mut ctx = TestContext {
+            targets: targets,
+            mix: &mix,
+            values: ValueStrategy::Uniform,
+            coverage: None,
+            guide: None,
+            failed_case: None,
+            repeat_hardware: false,
+            mem_regions: mem_regions,
+            code_regions: &[],
+        };
+        let ret = f(&mut ctx);
+        (ret, ctx.failed_case.take())
+    }
+
+    #[test]
+    fn agreeing_targets_pass() {
+        let mut targets = vec![mock("hw", 0, HW), mock("emu", 0, EMU), mock("emu2", 0, EMU)];
+        let (ret, failed_case) = run(&mut targets, |ctx| test_rom(ctx, &[1, 2], &[0; 32]));
+        assert!(ret.is_ok());
+        assert!(failed_case.is_none());
+    }
+
+    #[test]
+    fn mismatch_reports_agreeing_subsets() {
+        let mut targets = vec![mock("hw", 0, HW), mock("emu", 1, EMU), mock("emu2", 0, EMU), mock("emu3", 1, EMU)];
+        let (ret, failed_case) = run(&mut targets, |ctx| test_rom(ctx, &[1, 2], &[0; 32]));
+        match ret {
+            Err(TestError::Mismatch { agreeing, .. }) => {
+                assert_eq!(agreeing, vec![vec!["hw".to_string(), "emu2".to_string()], vec!["emu".to_string(), "emu3".to_string()]]);
+            }
+            ret => panic!("Expected a mismatch, got {:?}", ret),
+        }
+        assert_eq!(failed_case.unwrap().rom, vec![1, 2]);
+    }
+
+    #[test]
+    fn hardware_disagreement_is_nondeterministic() {
+        let mut targets = vec![mock("hw", 0, HW), mock("hw2", 1, HW), mock("emu", 0, EMU)];
+        let (ret, failed_case) = run(&mut targets, |ctx| test_rom(ctx, &[1, 2], &[0; 32]));
+        match ret {
+            Err(TestError::Nondeterministic { agreeing, .. }) => {
+                assert_eq!(agreeing, vec![vec!["hw".to_string()], vec!["hw2".to_string()]]);
+            }
+            ret => panic!("Expected nondeterminism, got {:?}", ret),
+        }
+        assert!(failed_case.is_some());
+    }
+
+    #[test]
+    fn captured_memory_starts_out_the_same() {
+        // Both targets hold different leftovers from earlier tests
+        let caps = Capabilities { read_mem: true, ..EMU };
+        let targets = || -> Vec<Box<Target>> {
+            vec![
+                Box::new(MockTarget::new("hw", |rom: &[u8], _: &[u32]| completed(rom, 0), Capabilities { hardware: true, ..caps }).with_leftover(0xff)),
+                Box::new(MockTarget::new("emu", |rom: &[u8], _: &[u32]| completed(rom, 0), caps)),
+            ]
+        };
+        // Takes in some loader globals too, which are never filled or compared
+        let mem_regions = vec![MemRegion::preset("scratch").unwrap(), MemRegion::new("globals", 0x05000300, 0x200)];
+
+        let mut unfilled = targets();
+        let captures = unfilled.iter_mut().map(|target| memory::capture(target, &mem_regions).unwrap()).collect::<Vec<_>>();
+        assert!(captures[0] != captures[1]);
+
+        let (ret, failed_case) = run_capturing(&mut targets(), &mem_regions, |ctx| test_rom(ctx, &[1, 2], &[0; 32]));
+        assert!(ret.is_ok(), "{:?}", ret);
+        assert!(failed_case.is_none());
+    }
+
+    #[test]
+    fn batch_failure_names_the_case() {
+        // Only disagrees about roms starting with 2
+        let emu = Box::new(MockTarget::new("emu", |rom: &[u8], _: &[u32]| completed(rom, if rom[0] == 2 { 1 } else { 0 }), EMU));
+        let mut targets: Vec<Box<Target>> = vec![mock("hw", 0, HW), emu];
+        let cases = (1..4).map(|first_byte| TestCase::new(vec![first_byte, 0], vec![0; 32])).collect::<Vec<_>>();
+        let (ret, failed_case) = run(&mut targets, |ctx| test_rom_batch(ctx, &cases));
+        match ret {
+            Err(TestError::BatchCase { index, ref error }) => {
+                assert_eq!(index, 1);
+                match **error {
+                    TestError::Mismatch { .. } => (),
+                    ref error => panic!("Expected a mismatch, got {:?}", error),
+                }
+            }
+            ret => panic!("Expected a batch case failure, got {:?}", ret),
+        }
+        assert_eq!(failed_case.unwrap().rom, vec![2, 0]);
+    }
+}
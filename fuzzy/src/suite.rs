@@ -0,0 +1,1028 @@
+use rand::{Rng, StdRng};
+
+use cache::CacheSelfModify;
+use dataflow::DataflowGenerator;
+use generators::*;
+use guided::Guide;
+use hazards::HazardGenerator;
+use runner::*;
+use timer::TimerInterrupts;
+use values::ValueStrategy;
+use vip::VipRegs;
+use vram::VramScene;
+
+pub type TestFn = fn(&mut TestContext, usize) -> Result<(), TestError>;
+
+// Hardware runs can land a tick apart just from where in a tick they start
+const TIMING_TOLERANCE: u32 = 1;
+
+pub fn all_tests() -> Vec<(TestFn, &'static str)> {
+    macro_rules! test {
+        ($name:ident) => (($name as TestFn, stringify!($name)));
+    }
+
+    vec![
+        test!(single_ret),
+        test!(muls),
+        test!(stsr_psws),
+        test!(multi1),
+        test!(moveas),
+        test!(multi2),
+        test!(movhis),
+        test!(multi3),
+        test!(mov_regs),
+        test!(mov_imms),
+        test!(mulus),
+        test!(nots),
+        test!(ors),
+        test!(oris),
+        test!(sar_regs),
+        test!(sar_imms),
+        test!(setfs),
+        test!(shl_regs),
+        test!(shl_imms),
+        test!(shr_regs),
+        test!(shr_imms),
+        test!(subs),
+        test!(xors),
+        test!(xoris),
+        test!(add_regs),
+        test!(add_imms),
+        test!(addis),
+        test!(ands),
+        test!(andis),
+        test!(cmp_regs),
+        test!(cmp_imms),
+        test!(mpyhws),
+        test!(revs),
+        test!(xbs),
+        test!(xhs),
+        test!(divs),
+        test!(divus),
+        test!(multi_all),
+        test!(multi_all_stsr_psws),
+        test!(multi_all_branches),
+        test!(single_rets_batched),
+        test!(muls_batched),
+        test!(multi_all_dataflow),
+        test!(multi_all_dataflow_branches),
+        test!(guided_all),
+        test!(multi_all_hazards),
+        test!(vip_regs),
+        test!(timer_interrupts),
+        test!(cache_self_modify),
+    ]
+}
+
+pub fn single_ret(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let case = single_ret_case(initial_seed, ctx.values);
+
+    test_rom(ctx, &case.rom, &case.initial_regs)
+}
+
+pub fn single_rets_batched(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let cases = (0..32).map(|_| single_ret_case(rng.gen::<usize>(), ctx.values)).collect::<Vec<_>>();
+
+    test_rom_batch(ctx, &cases)
+}
+
+pub fn single_ret_case(initial_seed: usize, values: ValueStrategy) -> TestCase {
+    let mut rom = Vec::new();
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(initial_seed), values);
+
+    TestCase::new(rom, initial_regs)
+}
+
+pub fn muls(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let case = muls_case(initial_seed, ctx.values);
+
+    test_rom(ctx, &case.rom, &case.initial_regs)
+}
+
+pub fn muls_batched(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let cases = (0..32).map(|_| muls_case(rng.gen::<usize>(), ctx.values)).collect::<Vec<_>>();
+
+    test_rom_batch(ctx, &cases)
+}
+
+pub fn muls_case(initial_seed: usize, values: ValueStrategy) -> TestCase {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut mul = Mul::new(build_rng(rng.gen::<usize>()));
+
+    // We use a particularly low number here, as otherwise values from r0 will propagate to all other regs eventually.
+    //  Need to make sure we test mul more thoroughly among other instr's as well in other tests.
+    for _ in 0..100 {
+        mul.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), values);
+
+    TestCase::new(rom, initial_regs)
+}
+
+pub fn stsr_psws(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut stsr_psw = StsrPsw::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        stsr_psw.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn moveas(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut movea = Movea::new(build_rng(rng.gen::<usize>()), ctx.values);
+
+    for _ in 0..1000 {
+        movea.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn movhis(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut movhi = Movhi::new(build_rng(rng.gen::<usize>()), ctx.values);
+
+    for _ in 0..1000 {
+        movhi.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn mov_regs(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut mov_reg = MovReg::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        mov_reg.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn mov_imms(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut mov_imm = MovImm::new(build_rng(rng.gen::<usize>()), ctx.values);
+
+    for _ in 0..1000 {
+        mov_imm.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn mulus(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut mulu = Mulu::new(build_rng(rng.gen::<usize>()));
+
+    // We use a particularly low number here, as otherwise values from r0 will propagate to all other regs eventually.
+    //  Need to make sure we test mulu more thoroughly among other instr's as well in other tests.
+    for _ in 0..100 {
+        mulu.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn nots(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut not = Not::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        not.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn ors(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut or = Or::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        or.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn oris(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut ori = Ori::new(build_rng(rng.gen::<usize>()), ctx.values);
+
+    for _ in 0..1000 {
+        ori.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn sar_regs(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut sar_reg = SarReg::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        sar_reg.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn sar_imms(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut sar_imm = SarImm::new(build_rng(rng.gen::<usize>()), ctx.values);
+
+    for _ in 0..1000 {
+        sar_imm.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn setfs(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut setf = Setf::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        setf.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn shl_regs(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut shl_reg = ShlReg::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        shl_reg.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn shl_imms(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut shl_imm = ShlImm::new(build_rng(rng.gen::<usize>()), ctx.values);
+
+    for _ in 0..1000 {
+        shl_imm.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn shr_regs(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut shr_reg = ShrReg::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        shr_reg.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn shr_imms(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut shr_imm = ShrImm::new(build_rng(rng.gen::<usize>()), ctx.values);
+
+    for _ in 0..1000 {
+        shr_imm.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn subs(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut sub = Sub::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        sub.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn xors(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut xor = Xor::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        xor.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn xoris(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut xori = Xori::new(build_rng(rng.gen::<usize>()), ctx.values);
+
+    for _ in 0..1000 {
+        xori.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn add_regs(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut add_reg = AddReg::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        add_reg.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn add_imms(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut add_imm = AddImm::new(build_rng(rng.gen::<usize>()), ctx.values);
+
+    for _ in 0..1000 {
+        add_imm.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn addis(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut addi = AddI::new(build_rng(rng.gen::<usize>()), ctx.values);
+
+    for _ in 0..1000 {
+        addi.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn ands(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut and = And::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        and.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn andis(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut andi = AndI::new(build_rng(rng.gen::<usize>()), ctx.values);
+
+    for _ in 0..1000 {
+        andi.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn cmp_regs(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut cmp_reg = CmpReg::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        cmp_reg.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn cmp_imms(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut cmp_imm = CmpImm::new(build_rng(rng.gen::<usize>()), ctx.values);
+
+    for _ in 0..1000 {
+        cmp_imm.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn mpyhws(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut mpyhw = Mpyhw::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        mpyhw.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn revs(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut rev = Rev::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        rev.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn xbs(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut xb = Xb::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        xb.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn xhs(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut xh = Xh::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        xh.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn divs(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut div = Div::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        div.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn divus(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut divu = Divu::new(build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        divu.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+    
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn multi1(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mul = Mul::new(build_rng(rng.gen::<usize>()));
+    let stsr_psw = StsrPsw::new(build_rng(rng.gen::<usize>()));
+    let mut gen = MultiGenerator::new(vec![Box::new(mul), Box::new(stsr_psw)], build_rng(rng.gen::<usize>()));
+
+    // We use a particularly low number here, as otherwise values from r0 will propagate to all other regs eventually.
+    //  Need to make sure we test mul more thoroughly among other instr's as well in other tests.
+    for _ in 0..200 {
+        gen.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn multi2(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mul = Mul::new(build_rng(rng.gen::<usize>()));
+    let stsr_psw = StsrPsw::new(build_rng(rng.gen::<usize>()));
+    let movea = Movea::new(build_rng(rng.gen::<usize>()), ctx.values);
+    let mut gen = MultiGenerator::new(vec![
+        Box::new(mul), 
+        Box::new(stsr_psw),
+        Box::new(movea),
+    ], build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        gen.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn multi3(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mul = Mul::new(build_rng(rng.gen::<usize>()));
+    let stsr_psw = StsrPsw::new(build_rng(rng.gen::<usize>()));
+    let movea = Movea::new(build_rng(rng.gen::<usize>()), ctx.values);
+    let movhi = Movhi::new(build_rng(rng.gen::<usize>()), ctx.values);
+    let mut gen = MultiGenerator::new(vec![
+        Box::new(mul), 
+        Box::new(stsr_psw),
+        Box::new(movea),
+        Box::new(movhi),
+    ], build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        gen.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn multi_all(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut gen = build_all_generator(&mut rng, ctx.mix, ctx.values);
+
+    for _ in 0..4000 {
+        gen.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)?;
+
+    Ok(())
+}
+
+pub fn multi_all_stsr_psws(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let all = build_all_generator(&mut rng, ctx.mix, ctx.values);
+    let stsr_psw = StsrPsw::new(build_rng(rng.gen::<usize>()));
+    let mut gen = AlternatingGenerator::new(Box::new(all), Box::new(stsr_psw));
+
+    for _ in 0..4000 {
+        gen.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)?;
+
+    Ok(())
+}
+
+pub fn multi_all_branches(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let all = build_all_generator(&mut rng, ctx.mix, ctx.values);
+    let mut gen = BranchingGenerator::new(Box::new(all), build_rng(rng.gen::<usize>()));
+
+    // Less iterations, as the branching generator will generate full blocks, not invididual instrs
+    for _ in 0..1000 {
+        gen.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)?;
+
+    Ok(())
+}
+
+pub fn multi_all_dataflow(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let all = build_all_generator(&mut rng, ctx.mix, ctx.values);
+    let mut gen = DataflowGenerator::new(Box::new(all), build_rng(rng.gen::<usize>()), ctx.values);
+
+    for _ in 0..4000 {
+        gen.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn multi_all_dataflow_branches(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    // Dataflow goes inside the blocks so it never sees (or rewrites) the branches themselves
+    let all = build_all_generator(&mut rng, ctx.mix, ctx.values);
+    let dataflow = DataflowGenerator::new(Box::new(all), build_rng(rng.gen::<usize>()), ctx.values);
+    let mut gen = BranchingGenerator::new(Box::new(dataflow), build_rng(rng.gen::<usize>()));
+
+    for _ in 0..1000 {
+        gen.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn multi_all_hazards(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let all = build_all_generator(&mut rng, ctx.mix, ctx.values);
+    let mut gen = HazardGenerator::new(Box::new(all), build_rng(rng.gen::<usize>()));
+
+    // Each one is a hazard pattern with a little context in front
+    for _ in 0..1000 {
+        gen.next(&mut rom);
+    }
+
+    Ret.next(&mut rom);
+
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom_timed(ctx, &rom, &initial_regs, TIMING_TOLERANCE)
+}
+
+pub fn guided_all(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    // Without a guide from the caller this still works, it just doesn't learn anything across tests
+    let mut local_guide = Guide::new("emu");
+    match ctx.guide.take() {
+        Some(guide) => {
+            let ret = guided_all_with_guide(ctx, guide, initial_seed);
+            ctx.guide = Some(guide);
+            ret
+        }
+        _ => guided_all_with_guide(ctx, &mut local_guide, initial_seed),
+    }
+}
+
+fn guided_all_with_guide(ctx: &mut TestContext, guide: &mut Guide, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let screen_target = guide.screen_target().to_string();
+
+    for _ in 0..32 {
+        let (rom, initial_regs, picks) = if !guide.corpus().is_empty() && rng.gen_weighted_bool(4) {
+            // Revisit an interesting program with fresh regs
+            let entry = &guide.corpus()[rng.gen::<usize>() % guide.corpus().len()];
+            (entry.rom.clone(), random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values), Vec::new())
+        } else {
+            let mut gen = build_all_generator(&mut rng, ctx.mix, ctx.values);
+            guide.tune(&mut gen);
+
+            // Short programs, so novelty can be pinned on the generators that were actually picked
+            let mut rom = Vec::new();
+            for _ in 0..200 {
+                gen.next(&mut rom);
+            }
+
+            Ret.next(&mut rom);
+
+            let picks = gen.picks().iter().map(|&(name, picks)| (name.to_string(), picks)).collect::<Vec<_>>();
+            (rom, random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values), picks)
+        };
+
+        match run_on_target(ctx, &screen_target, &rom, &initial_regs)? {
+            Some(result) => {
+                // Only novel candidates are worth the other (slower) targets' time
+                if guide.observe(&rom, &initial_regs, &result, &picks) > 0 {
+                    test_rom_with_known_result(ctx, &rom, &initial_regs, Some((&screen_target, &result)))?;
+                }
+            }
+            // Nothing to screen on, so everything gets compared
+            _ => test_rom(ctx, &rom, &initial_regs)?,
+        }
+    }
+
+    Ok(())
+}
+
+pub fn vip_regs(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut vip_regs = VipRegs::new(build_rng(rng.gen::<usize>()));
+    vip_regs.next(&mut rom);
+
+    Ret.next(&mut rom);
+
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn timer_interrupts(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut timer_interrupts = TimerInterrupts::new(build_rng(rng.gen::<usize>()));
+    timer_interrupts.next(&mut rom);
+
+    Ret.next(&mut rom);
+
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom(ctx, &rom, &initial_regs)
+}
+
+pub fn cache_self_modify(ctx: &mut TestContext, initial_seed: usize) -> Result<(), TestError> {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut cache_self_modify = CacheSelfModify::new(build_rng(rng.gen::<usize>()));
+    cache_self_modify.next(&mut rom);
+
+    Ret.next(&mut rom);
+
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), ctx.values);
+
+    test_rom_timed(ctx, &rom, &initial_regs, TIMING_TOLERANCE)
+}
+
+// Not a test on its own; see the --render mode
+pub fn vram_scene_case(initial_seed: usize, values: ValueStrategy) -> TestCase {
+    let mut rng = build_rng(initial_seed);
+
+    let mut rom = Vec::new();
+
+    let mut scene = VramScene::new(build_rng(rng.gen::<usize>()));
+    scene.next(&mut rom);
+
+    Ret.next(&mut rom);
+
+    let initial_regs = random_regs(&mut build_rng(rng.gen::<usize>()), values);
+
+    TestCase::new(rom, initial_regs)
+}
+
+pub fn random_regs(rng: &mut StdRng, values: ValueStrategy) -> Vec<u32> {
+    // Initial regs cover r0-r31 inclusive
+    (0..32).map(|_| values.gen_bits(rng, 32)).collect::<Vec<_>>()
+}
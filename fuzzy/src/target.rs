@@ -0,0 +1,281 @@
+use command::{self, ExecuteResult};
+use trace::TraceEntry;
+
+use std::io::{Read, Write};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Capabilities {
+    // execute_batch is supported (results come back through read_mem, so this needs read_mem too)
+    pub batch: bool,
+    // read_mem is supported, so memory can be captured and compared after tests
+    pub read_mem: bool,
+    // elapsed_ticks in results reflect real (or emulated) execution time
+    pub timing: bool,
+    // recover can bring the target back after a dispatch failure
+    pub recover: bool,
+    // start_run_test/finish_run_test are supported, so other targets can run while this one works
+    pub background: bool,
+    // Results come from a real VB, so they're what everything else is judged against
+    pub hardware: bool,
+}
+
+// Something that can run test roms through the execute harness and report the results.
+pub trait Target {
+    fn name(&self) -> &str;
+    fn capabilities(&self) -> Capabilities;
+
+    fn upload(&mut self, addr: u32, data: &[u8]) -> Result<(), command::Error>;
+    fn set_regs(&mut self, initial_regs: &[u32]) -> Result<(), command::Error>;
+    fn execute(&mut self, entry: u32) -> Result<(), command::Error>;
+    fn read_results(&mut self) -> Result<ExecuteResult, command::Error>;
+
+    fn read_mem(&mut self, addr: u32, length: u32) -> Result<Vec<u8>, command::Error>;
+    // num_cases has to match the table, and bounds how long the target can take
+    fn execute_batch(&mut self, table: u32, num_cases: usize) -> Result<(), command::Error>;
+
+    fn run_test(&mut self, entry: u32, rom: &[u8], initial_regs: &[u32]) -> Result<ExecuteResult, command::Error> {
+        self.upload(entry, rom)?;
+        self.set_regs(initial_regs)?;
+        self.execute(entry)?;
+        self.read_results()
+    }
+
+    // Puts the target back into a known state before a test. Targets that can't do that cheaply don't bother.
+    fn reset(&mut self) -> Result<(), command::Error> {
+        Ok(())
+    }
+
+    fn start_run_test(&mut self, _entry: u32, _rom: &[u8], _initial_regs: &[u32]) -> Result<(), command::Error> {
+        Err(command::Error::ProtocolViolation)
+    }
+
+    fn finish_run_test(&mut self) -> Result<ExecuteResult, command::Error> {
+        Err(command::Error::ProtocolViolation)
+    }
+
+    fn recover(&mut self) -> Result<(), String> {
+        Err(format!("Target `{}` doesn't support recovery", self.name()))
+    }
+
+    // Like run_test, but also returns every instruction the rom executed. None if the target can't trace.
+    fn trace_test(&mut self, _entry: u32, _rom: &[u8], _initial_regs: &[u32]) -> Result<Option<(ExecuteResult, Vec<TraceEntry>)>, command::Error> {
+        Ok(None)
+    }
+}
+
+// A port that speaks the loader protocol
+pub trait Link: Read + Write {
+    fn recover(&mut self) -> Result<(), String>;
+
+    fn reset(&mut self) {
+        // Real hardware keeps whatever state it's in
+    }
+
+    // Starts tracing instructions executed in [start, end), returning false if the link can't see that far
+    fn start_trace(&mut self, _start: u32, _end: u32) -> bool {
+        false
+    }
+
+    // Stops tracing and hands over what was traced
+    fn take_trace(&mut self) -> Vec<TraceEntry> {
+        Vec::new()
+    }
+}
+
+// Must match the execute harness
+const INITIAL_REGS_ADDR: u32 = 0x0001e000;
+const RESULT_WORDS_ADDR: u32 = INITIAL_REGS_ADDR + 32 * 4;
+
+pub struct LinkTarget<L: Link> {
+    name: String,
+    link: L,
+    capabilities: Capabilities,
+}
+
+impl<L: Link> LinkTarget<L> {
+    pub fn new<N: Into<String>>(name: N, link: L, capabilities: Capabilities) -> LinkTarget<L> {
+        LinkTarget {
+            name: name.into(),
+            link: link,
+            capabilities: capabilities,
+        }
+    }
+}
+
+impl<L: Link> Target for LinkTarget<L> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    fn upload(&mut self, addr: u32, data: &[u8]) -> Result<(), command::Error> {
+        command::write_mem_region(&mut self.link, addr, data)
+    }
+
+    fn set_regs(&mut self, initial_regs: &[u32]) -> Result<(), command::Error> {
+        if initial_regs.len() != command::NUM_INITIAL_REGS {
+            return Err(command::Error::WrongNumInitialRegs);
+        }
+
+        let initial_regs_bytes = initial_regs.iter().flat_map(|reg| {
+            (0..4).map(move |i| (reg >> (i * 8)) as u8)
+        }).collect::<Vec<_>>();
+
+        command::write_mem_region(&mut self.link, INITIAL_REGS_ADDR, &initial_regs_bytes)
+    }
+
+    fn execute(&mut self, entry: u32) -> Result<(), command::Error> {
+        command::execute(&mut self.link, entry)
+    }
+
+    fn read_results(&mut self) -> Result<ExecuteResult, command::Error> {
+        let mut tries = 0;
+        let result_bytes = loop {
+            match command::read_mem_region(&mut self.link, RESULT_WORDS_ADDR, (command::NUM_RESULT_WORDS * 4) as u32) {
+                Ok(result_bytes) => break result_bytes,
+                Err(e) => {
+                    tries += 1;
+                    if tries >= 200 {
+                        return Err(e);
+                    }
+                }
+            }
+        };
+
+        let mut result_words = Vec::new();
+        for i in 0..command::NUM_RESULT_WORDS {
+            let mut word = 0;
+            for j in 0..4 {
+                word >>= 8;
+                word |= (result_bytes[i * 4 + j] as u32) << 24;
+            }
+            result_words.push(word);
+        }
+
+        ExecuteResult::from_result_words(result_words)
+    }
+
+    fn read_mem(&mut self, addr: u32, length: u32) -> Result<Vec<u8>, command::Error> {
+        command::read_mem_region(&mut self.link, addr, length)
+    }
+
+    fn execute_batch(&mut self, table: u32, num_cases: usize) -> Result<(), command::Error> {
+        command::execute_batch(&mut self.link, table, num_cases)
+    }
+
+    fn run_test(&mut self, entry: u32, rom: &[u8], initial_regs: &[u32]) -> Result<ExecuteResult, command::Error> {
+        // Single round trip instead of the individual commands
+        command::run_test(&mut self.link, entry, rom, initial_regs)
+    }
+
+    fn reset(&mut self) -> Result<(), command::Error> {
+        self.link.reset();
+        Ok(())
+    }
+
+    fn recover(&mut self) -> Result<(), String> {
+        self.link.recover()
+    }
+
+    fn trace_test(&mut self, entry: u32, rom: &[u8], initial_regs: &[u32]) -> Result<Option<(ExecuteResult, Vec<TraceEntry>)>, command::Error> {
+        if !self.link.start_trace(entry, entry + rom.len() as u32) {
+            return Ok(None);
+        }
+
+        let result = self.run_test(entry, rom, initial_regs);
+        // Always stop tracing, even if the test didn't make it
+        let trace = self.link.take_trace();
+        result.map(|result| Some((result, trace)))
+    }
+}
+
+// In-process stand-in for a real target; behaviour computes the result for a rom and its initial regs.
+pub struct MockTarget<F: FnMut(&[u8], &[u32]) -> ExecuteResult> {
+    name: String,
+    behaviour: F,
+    capabilities: Capabilities,
+
+    uploads: Vec<(u32, Vec<u8>)>,
+    initial_regs: Vec<u32>,
+    result: Option<ExecuteResult>,
+
+    // What memory that was never uploaded to reads back as
+    leftover: u8,
+}
+
+impl<F: FnMut(&[u8], &[u32]) -> ExecuteResult> MockTarget<F> {
+    // Batches are always run case by case, whatever the capabilities say
+    pub fn new<N: Into<String>>(name: N, behaviour: F, capabilities: Capabilities) -> MockTarget<F> {
+        MockTarget {
+            name: name.into(),
+            behaviour: behaviour,
+            capabilities: capabilities,
+
+            uploads: Vec::new(),
+            initial_regs: Vec::new(),
+            result: None,
+
+            leftover: 0,
+        }
+    }
+
+    // Stands in for whatever earlier tests left in memory
+    pub fn with_leftover(mut self, leftover: u8) -> MockTarget<F> {
+        self.leftover = leftover;
+        self
+    }
+}
+
+impl<F: FnMut(&[u8], &[u32]) -> ExecuteResult> Target for MockTarget<F> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            batch: false,
+            ..self.capabilities
+        }
+    }
+
+    fn upload(&mut self, addr: u32, data: &[u8]) -> Result<(), command::Error> {
+        self.uploads.push((addr, data.to_vec()));
+        Ok(())
+    }
+
+    fn set_regs(&mut self, initial_regs: &[u32]) -> Result<(), command::Error> {
+        self.initial_regs = initial_regs.to_vec();
+        Ok(())
+    }
+
+    fn execute(&mut self, entry: u32) -> Result<(), command::Error> {
+        // Most recent upload covering the entry point wins
+        let rom = self.uploads.iter().rev()
+            .find(|&&(addr, ref data)| entry >= addr && entry < addr + data.len() as u32)
+            .map(|&(addr, ref data)| data[(entry - addr) as usize..].to_vec())
+            .ok_or(command::Error::ProtocolViolation)?;
+        self.result = Some((self.behaviour)(&rom, &self.initial_regs));
+        Ok(())
+    }
+
+    fn read_results(&mut self) -> Result<ExecuteResult, command::Error> {
+        self.result.clone().ok_or(command::Error::ProtocolViolation)
+    }
+
+    fn read_mem(&mut self, addr: u32, length: u32) -> Result<Vec<u8>, command::Error> {
+        // Most recent upload covering each byte wins
+        Ok((addr..addr + length).map(|byte_addr| {
+            self.uploads.iter().rev()
+                .find(|&&(upload_addr, ref data)| byte_addr >= upload_addr && byte_addr < upload_addr + data.len() as u32)
+                .map(|&(upload_addr, ref data)| data[(byte_addr - upload_addr) as usize])
+                .unwrap_or(self.leftover)
+        }).collect())
+    }
+
+    fn execute_batch(&mut self, _table: u32, _num_cases: usize) -> Result<(), command::Error> {
+        Err(command::Error::ProtocolViolation)
+    }
+}
@@ -1,10 +1,59 @@
 use serialport;
 use serialport::prelude::*;
 
-use std::ffi::OsStr;
-use std::io::Read;
+use command;
+use target::Link;
+
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Read, Write};
+use std::thread;
 use std::time::Duration;
 
+pub struct TeensyVbLink {
+    // Only None while recovering, or after recovery couldn't reopen the port
+    port: Option<Box<SerialPort>>,
+    port_name: OsString,
+}
+
+impl TeensyVbLink {
+    pub fn connect<P: AsRef<OsStr>>(port_name: P) -> Result<TeensyVbLink, String> {
+        let port = connect(&port_name)?;
+
+        Ok(TeensyVbLink {
+            port: Some(port),
+            port_name: port_name.as_ref().to_os_string(),
+        })
+    }
+}
+
+impl TeensyVbLink {
+    fn port(&mut self) -> io::Result<&mut Box<SerialPort>> {
+        self.port.as_mut().ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "Serial port closed after a failed recovery"))
+    }
+}
+
+impl Read for TeensyVbLink {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.port()?.read(buf)
+    }
+}
+
+impl Write for TeensyVbLink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.port()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.port()?.flush()
+    }
+}
+
+impl Link for TeensyVbLink {
+    fn recover(&mut self) -> Result<(), String> {
+        recover(&mut self.port, &self.port_name)
+    }
+}
+
 pub fn connect<P: AsRef<OsStr>>(port: P) -> Result<Box<SerialPort>, String> {
     let mut tries = 0;
     loop {
@@ -26,6 +75,34 @@ pub fn connect<P: AsRef<OsStr>>(port: P) -> Result<Box<SerialPort>, String> {
     }
 }
 
+pub fn recover<P: AsRef<OsStr>>(port: &mut Option<Box<SerialPort>>, port_name: P) -> Result<(), String> {
+    // Dropping DTR sends the teensy back to its connect loop, abandoning any half-finished exchange. The old handle
+    //  has to be closed before reopening, since some platforms (Windows, at least) won't open a port twice.
+    if let Some(mut old_port) = port.take() {
+        old_port.write_data_terminal_ready(false).map_err(|e| format!("Couldn't clear serial port DTR: {}", e))?;
+    }
+    thread::sleep(Duration::from_millis(100));
+
+    let port = port.get_or_insert(connect(port_name)?);
+
+    // The loader may still be waiting for the tail end of an earlier command, and the execute harness watchdog
+    //  may not have fired yet, so keep checking status until the loader is back at the top of its command loop
+    let mut tries = 0;
+    loop {
+        match command::check_status(port) {
+            Ok(()) => {
+                return Ok(());
+            }
+            Err(e) => {
+                tries += 1;
+                if tries >= 10 {
+                    return Err(format!("Loader didn't respond after reconnecting: {:?}", e));
+                }
+            }
+        }
+    }
+}
+
 fn wait_for_handshake<R: Read>(r: &mut R) -> Result<(), String> {
     let handshake = b"HANDSHAKE YO";
     let mut handshake_buf = vec![0; handshake.len()];
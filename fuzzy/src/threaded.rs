@@ -0,0 +1,229 @@
+use command::{self, ExecuteResult};
+use target::{Capabilities, Target};
+use trace::TraceEntry;
+use transport;
+
+use std::io;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+enum Request {
+    Upload(u32, Vec<u8>),
+    SetRegs(Vec<u32>),
+    Execute(u32),
+    ReadResults,
+    ReadMem(u32, u32),
+    ExecuteBatch(u32, usize),
+    RunTest(u32, Vec<u8>, Vec<u32>),
+    Reset,
+    Recover,
+    TraceTest(u32, Vec<u8>, Vec<u32>),
+}
+
+enum Response {
+    Done(Result<(), command::Error>),
+    Result(Result<ExecuteResult, command::Error>),
+    Mem(Result<Vec<u8>, command::Error>),
+    Recovered(Result<(), String>),
+    Trace(Result<Option<(ExecuteResult, Vec<TraceEntry>)>, command::Error>),
+}
+
+// Drives another target from its own thread, so a slow (link-bound) target can run a test while the caller runs the
+//  same test somewhere else. The inner target is built on the worker thread too, so it doesn't need to be Send.
+pub struct ThreadedTarget {
+    name: String,
+    capabilities: Capabilities,
+
+    requests: Option<Sender<Request>>,
+    responses: Receiver<Response>,
+    // A started test whose result hasn't been collected yet
+    pending: bool,
+
+    worker: Option<JoinHandle<()>>,
+}
+
+impl ThreadedTarget {
+    pub fn spawn<F: FnOnce() -> Result<Box<Target>, String> + Send + 'static>(build_target: F) -> Result<ThreadedTarget, String> {
+        let (request_sender, request_receiver) = channel();
+        let (response_sender, response_receiver) = channel();
+        let (info_sender, info_receiver) = channel();
+
+        let worker = thread::spawn(move || {
+            let mut target = match build_target() {
+                Ok(target) => target,
+                Err(e) => {
+                    let _ = info_sender.send(Err(e));
+                    return;
+                }
+            };
+            if info_sender.send(Ok((target.name().to_string(), target.capabilities()))).is_err() {
+                return;
+            }
+
+            // Runs until the ThreadedTarget hangs up
+            for request in request_receiver.iter() {
+                let response = match request {
+                    Request::Upload(addr, data) => Response::Done(target.upload(addr, &data)),
+                    Request::SetRegs(initial_regs) => Response::Done(target.set_regs(&initial_regs)),
+                    Request::Execute(entry) => Response::Done(target.execute(entry)),
+                    Request::ReadResults => Response::Result(target.read_results()),
+                    Request::ReadMem(addr, length) => Response::Mem(target.read_mem(addr, length)),
+                    Request::ExecuteBatch(table, num_cases) => Response::Done(target.execute_batch(table, num_cases)),
+                    Request::RunTest(entry, rom, initial_regs) => Response::Result(target.run_test(entry, &rom, &initial_regs)),
+                    Request::Reset => Response::Done(target.reset()),
+                    Request::Recover => Response::Recovered(target.recover()),
+                    Request::TraceTest(entry, rom, initial_regs) => Response::Trace(target.trace_test(entry, &rom, &initial_regs)),
+                };
+                if response_sender.send(response).is_err() {
+                    return;
+                }
+            }
+        });
+
+        let (name, capabilities) = info_receiver.recv().map_err(|_| String::from("Target thread exited during startup"))??;
+
+        Ok(ThreadedTarget {
+            name: name,
+            capabilities: Capabilities {
+                background: true,
+                ..capabilities
+            },
+
+            requests: Some(request_sender),
+            responses: response_receiver,
+            pending: false,
+
+            worker: Some(worker),
+        })
+    }
+
+    fn send(&mut self, request: Request) -> Result<(), command::Error> {
+        // A test that was started but never finished (eg. because another target failed first) still owes us a
+        //  response, which mustn't be mistaken for the one to this request
+        if self.pending {
+            self.pending = false;
+            self.responses.recv().map_err(|_| worker_gone())?;
+        }
+
+        match self.requests {
+            Some(ref requests) => requests.send(request).map_err(|_| worker_gone()),
+            _ => Err(worker_gone()),
+        }
+    }
+
+    fn request(&mut self, request: Request) -> Result<Response, command::Error> {
+        self.send(request)?;
+        self.responses.recv().map_err(|_| worker_gone())
+    }
+}
+
+fn worker_gone() -> command::Error {
+    command::Error::Transport(transport::Error::Io(io::Error::new(io::ErrorKind::BrokenPipe, "Target thread exited")))
+}
+
+impl Target for ThreadedTarget {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    fn upload(&mut self, addr: u32, data: &[u8]) -> Result<(), command::Error> {
+        match self.request(Request::Upload(addr, data.to_vec()))? {
+            Response::Done(ret) => ret,
+            _ => Err(command::Error::ProtocolViolation),
+        }
+    }
+
+    fn set_regs(&mut self, initial_regs: &[u32]) -> Result<(), command::Error> {
+        match self.request(Request::SetRegs(initial_regs.to_vec()))? {
+            Response::Done(ret) => ret,
+            _ => Err(command::Error::ProtocolViolation),
+        }
+    }
+
+    fn execute(&mut self, entry: u32) -> Result<(), command::Error> {
+        match self.request(Request::Execute(entry))? {
+            Response::Done(ret) => ret,
+            _ => Err(command::Error::ProtocolViolation),
+        }
+    }
+
+    fn read_results(&mut self) -> Result<ExecuteResult, command::Error> {
+        match self.request(Request::ReadResults)? {
+            Response::Result(ret) => ret,
+            _ => Err(command::Error::ProtocolViolation),
+        }
+    }
+
+    fn read_mem(&mut self, addr: u32, length: u32) -> Result<Vec<u8>, command::Error> {
+        match self.request(Request::ReadMem(addr, length))? {
+            Response::Mem(ret) => ret,
+            _ => Err(command::Error::ProtocolViolation),
+        }
+    }
+
+    fn execute_batch(&mut self, table: u32, num_cases: usize) -> Result<(), command::Error> {
+        match self.request(Request::ExecuteBatch(table, num_cases))? {
+            Response::Done(ret) => ret,
+            _ => Err(command::Error::ProtocolViolation),
+        }
+    }
+
+    fn run_test(&mut self, entry: u32, rom: &[u8], initial_regs: &[u32]) -> Result<ExecuteResult, command::Error> {
+        self.start_run_test(entry, rom, initial_regs)?;
+        self.finish_run_test()
+    }
+
+    fn reset(&mut self) -> Result<(), command::Error> {
+        match self.request(Request::Reset)? {
+            Response::Done(ret) => ret,
+            _ => Err(command::Error::ProtocolViolation),
+        }
+    }
+
+    fn start_run_test(&mut self, entry: u32, rom: &[u8], initial_regs: &[u32]) -> Result<(), command::Error> {
+        self.send(Request::RunTest(entry, rom.to_vec(), initial_regs.to_vec()))?;
+        self.pending = true;
+        Ok(())
+    }
+
+    fn finish_run_test(&mut self) -> Result<ExecuteResult, command::Error> {
+        if !self.pending {
+            return Err(command::Error::ProtocolViolation);
+        }
+        self.pending = false;
+
+        match self.responses.recv().map_err(|_| worker_gone())? {
+            Response::Result(ret) => ret,
+            _ => Err(command::Error::ProtocolViolation),
+        }
+    }
+
+    fn recover(&mut self) -> Result<(), String> {
+        match self.request(Request::Recover) {
+            Ok(Response::Recovered(ret)) => ret,
+            Ok(_) => Err("Target thread sent an unexpected response".into()),
+            Err(e) => Err(format!("Couldn't reach target thread: {:?}", e)),
+        }
+    }
+
+    fn trace_test(&mut self, entry: u32, rom: &[u8], initial_regs: &[u32]) -> Result<Option<(ExecuteResult, Vec<TraceEntry>)>, command::Error> {
+        match self.request(Request::TraceTest(entry, rom.to_vec(), initial_regs.to_vec()))? {
+            Response::Trace(ret) => ret,
+            _ => Err(command::Error::ProtocolViolation),
+        }
+    }
+}
+
+impl Drop for ThreadedTarget {
+    fn drop(&mut self) {
+        // Hanging up ends the worker's request loop
+        self.requests = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
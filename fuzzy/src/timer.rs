@@ -0,0 +1,139 @@
+use rand::{Rng, StdRng};
+
+use asm::*;
+use generators::Generator;
+
+// Timer regs, as offsets from 0x02000000 (byte wide)
+const TLR: i16 = 0x18;
+const THR: i16 = 0x1c;
+const TCR: i16 = 0x20;
+
+const TCR_ENABLE: u16 = 0x01;
+const TCR_ZERO_STATUS_CLEAR: u16 = 0x04;
+const TCR_INTERRUPT_ENABLE: u16 = 0x08;
+const TCR_CLOCK_SELECT_20US: u16 = 0x10;
+
+// The execute harness counts timer interrupts here for tests that set a budget (see execute_harness.s)
+const TIMER_RECORD_HI: u16 = 0x0002;
+// 0x00020000 - 0x1e80 = 0x0001e180
+const TIMER_RECORD_LO: i16 = -0x1e80;
+const TIMER_RECORD_BUDGET: i16 = 0;
+const TIMER_RECORD_COUNT: i16 = 4;
+const TIMER_RECORD_LAST_EIPC: i16 = 8;
+const TIMER_RECORD_LAST_EIPSW: i16 = 12;
+
+// Regs the program keeps for itself. The harness's interrupt handler saves and restores everything it touches,
+//  so the program has all the others.
+const TIMER_REG: u16 = 10;
+const RECORD_REG: u16 = 11;
+const VALUE_REG: u16 = 12;
+const COUNTER_REG: u16 = 13;
+// Readbacks taken along the way go from here up to the final snapshot regs
+const FIRST_READBACK_REG: u16 = 14;
+const COUNT_REG: u16 = 28;
+const LAST_EIPC_REG: u16 = 29;
+const LAST_EIPSW_REG: u16 = 30;
+const TCR_REG: u16 = 31;
+
+const NUM_SEGMENTS: usize = 32;
+const MAX_DELAY_ITERATIONS: u16 = 0x2000;
+// Short enough for a handful of interrupts over a test, even at 100us ticks
+const MAX_RELOAD: u16 = 0x100;
+
+// Programs the timer (reload, interval and interrupts), takes over its interrupt from the harness's watchdog, and
+//  runs delay loops in between reading back the counter and the number of interrupts so far. The counter stops
+//  once the workload is done, and the interrupt count, the last interrupt's EIPC/EIPSW and TCR end up in r28-r31.
+//
+// There's no watchdog while this runs, since the timer is busy. Every loop is bounded, and the interrupt budget
+//  hands control back to the harness as a timeout if interrupts keep firing regardless.
+pub struct TimerInterrupts {
+    rng: StdRng,
+}
+
+impl TimerInterrupts {
+    pub fn new(rng: StdRng) -> TimerInterrupts {
+        TimerInterrupts {
+            rng: rng,
+        }
+    }
+
+    fn random_reload(&mut self) -> u16 {
+        self.rng.gen_range(1, MAX_RELOAD + 1)
+    }
+
+    fn random_tcr(&mut self) -> u16 {
+        let mut ret = TCR_ENABLE;
+        if self.rng.gen() {
+            ret |= TCR_CLOCK_SELECT_20US;
+        }
+        // Mostly with interrupts, but without now and then so a pending zero status can show up when they come back
+        if self.rng.gen_weighted_bool(8) {
+            ret
+        } else {
+            ret | TCR_INTERRUPT_ENABLE
+        }
+    }
+
+    fn write_reload(&mut self, buf: &mut Vec<u8>) {
+        let reload = self.random_reload();
+        movea(buf, reload as i16, 0, VALUE_REG);
+        st_b(buf, VALUE_REG, TLR, TIMER_REG);
+        shr_imm(buf, 8, VALUE_REG);
+        st_b(buf, VALUE_REG, THR, TIMER_REG);
+    }
+}
+
+impl Generator for TimerInterrupts {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        movhi(buf, 0x0200, 0, TIMER_REG);
+        movhi(buf, TIMER_RECORD_HI, 0, RECORD_REG);
+        movea(buf, TIMER_RECORD_LO, RECORD_REG, RECORD_REG);
+
+        // Take the timer over from the watchdog. The timer only counts while enabled, so the reload goes in first.
+        st_b(buf, 0, TCR, TIMER_REG);
+        movhi(buf, 0x0001, 0, VALUE_REG);
+        st_w(buf, VALUE_REG, TIMER_RECORD_BUDGET, RECORD_REG);
+        movea(buf, TCR_ZERO_STATUS_CLEAR as i16, 0, VALUE_REG);
+        st_b(buf, VALUE_REG, TCR, TIMER_REG);
+        self.write_reload(buf);
+        let tcr = self.random_tcr();
+        movea(buf, tcr as i16, 0, VALUE_REG);
+        st_b(buf, VALUE_REG, TCR, TIMER_REG);
+
+        let mut readback_reg = FIRST_READBACK_REG;
+        for _ in 0..NUM_SEGMENTS {
+            match self.rng.gen::<u32>() % 10 {
+                0 | 1 | 2 | 3 | 4 => {
+                    let iterations = self.rng.gen::<u16>() % MAX_DELAY_ITERATIONS + 1;
+                    movea(buf, iterations as i16, 0, COUNTER_REG);
+                    add_imm(buf, -1, COUNTER_REG);
+                    bcond(buf, COND_NZ, -2);
+                }
+                // Takes effect at the next reload
+                5 => self.write_reload(buf),
+                6 => {
+                    let tcr = self.random_tcr();
+                    movea(buf, tcr as i16, 0, VALUE_REG);
+                    st_b(buf, VALUE_REG, TCR, TIMER_REG);
+                }
+                7 | 8 if readback_reg + 1 < COUNT_REG => {
+                    ld_b(buf, TLR, TIMER_REG, readback_reg);
+                    ld_b(buf, THR, TIMER_REG, readback_reg + 1);
+                    readback_reg += 2;
+                }
+                _ if readback_reg < COUNT_REG => {
+                    ld_w(buf, TIMER_RECORD_COUNT, RECORD_REG, readback_reg);
+                    readback_reg += 1;
+                }
+                _ => (),
+            }
+        }
+
+        // Stop the timer before the snapshot, so nothing changes under it. TCR still has the zero status.
+        st_b(buf, 0, TCR, TIMER_REG);
+        ld_w(buf, TIMER_RECORD_COUNT, RECORD_REG, COUNT_REG);
+        ld_w(buf, TIMER_RECORD_LAST_EIPC, RECORD_REG, LAST_EIPC_REG);
+        ld_w(buf, TIMER_RECORD_LAST_EIPSW, RECORD_REG, LAST_EIPSW_REG);
+        ld_b(buf, TCR, TIMER_REG, TCR_REG);
+    }
+}
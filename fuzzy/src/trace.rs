@@ -0,0 +1,63 @@
+use disasm;
+
+// One executed instruction
+#[derive(Clone, Debug)]
+pub struct TraceEntry {
+    pub pc: u32,
+    // Only the regs whose values changed, as (reg, new value)
+    pub reg_writes: Vec<(usize, u32)>,
+    pub psw_before: u32,
+    pub psw_after: u32,
+}
+
+impl TraceEntry {
+    // regs_before/regs_after are r0-r31
+    pub fn new(pc: u32, regs_before: &[u32], regs_after: &[u32], psw_before: u32, psw_after: u32) -> TraceEntry {
+        TraceEntry {
+            pc: pc,
+            reg_writes: regs_before.iter().zip(regs_after.iter()).enumerate()
+                .filter(|&(_, (before, after))| before != after)
+                .map(|(reg, (_, &after))| (reg, after))
+                .collect(),
+            psw_before: psw_before,
+            psw_after: psw_after,
+        }
+    }
+}
+
+// Renders a trace one line per executed instruction, using the same offsets and disassembly as the program listing
+//  so the two can be read side by side. Anything outside the rom (eg. the harness) shows up as ???.
+pub fn format(trace: &[TraceEntry], rom: &[u8], rom_addr: u32) -> String {
+    let instructions = disasm::decode(rom);
+
+    let mut ret = String::new();
+    for entry in trace.iter() {
+        let offset = entry.pc.wrapping_sub(rom_addr) as usize;
+        let text = instructions.iter()
+            .find(|instruction| instruction.offset == offset)
+            .map(|instruction| instruction.disassemble())
+            .unwrap_or_else(|| String::from("???"));
+
+        let mut effects = entry.reg_writes.iter().map(|&(reg, value)| format!("r{}=0x{:08x}", reg, value)).collect::<Vec<_>>();
+        if entry.psw_before != entry.psw_after {
+            effects.push(format!("psw 0x{:08x}->0x{:08x} ({})", entry.psw_before, entry.psw_after, flag_changes(entry.psw_before, entry.psw_after)));
+        }
+
+        ret += &format!("0x{:04x}: {:<28} {}\n", offset, text, effects.join(" "));
+    }
+
+    ret
+}
+
+fn flag_changes(before: u32, after: u32) -> String {
+    let flags = [("z", disasm::FLAG_Z), ("s", disasm::FLAG_S), ("ov", disasm::FLAG_OV), ("cy", disasm::FLAG_CY)];
+
+    let mut ret = flags.iter()
+        .filter(|&&(_, flag)| (before ^ after) & flag != 0)
+        .map(|&(name, flag)| format!("{}{}", if after & flag != 0 { "+" } else { "-" }, name))
+        .collect::<Vec<_>>();
+    if (before ^ after) & !0x0f != 0 {
+        ret.push(String::from("other bits"));
+    }
+    ret.join(" ")
+}
@@ -17,7 +17,7 @@ pub fn exchange_packet<P: Read + Write>(port: &mut P, packet: &[u8]) -> Result<V
     // Send packet
     let packet_len = (packet.len() - 1) as u8;
     let packet_buf = [packet_len].iter().chain(packet.iter()).cloned().collect::<Vec<_>>();
-    port.write_all(&packet_buf).unwrap();
+    port.write_all(&packet_buf).map_err(|e| Error::Io(e))?;
 
     // Receive packet
     //  Receive length
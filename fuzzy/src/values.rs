@@ -0,0 +1,138 @@
+use rand::{Rng, StdRng};
+
+use std::cmp;
+
+// How register values and immediates are drawn. Uniform values almost never hit the corners where overflow, carry
+//  and sign-extension bugs live, so the other strategies aim for those deliberately.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ValueStrategy {
+    Uniform,
+    BoundaryBiased,
+    SmallMagnitude,
+    BitPattern,
+}
+
+pub const ALL_VALUE_STRATEGIES: [ValueStrategy; 4] = [
+    ValueStrategy::Uniform,
+    ValueStrategy::BoundaryBiased,
+    ValueStrategy::SmallMagnitude,
+    ValueStrategy::BitPattern,
+];
+
+impl ValueStrategy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            &ValueStrategy::Uniform => "uniform",
+            &ValueStrategy::BoundaryBiased => "boundary",
+            &ValueStrategy::SmallMagnitude => "small",
+            &ValueStrategy::BitPattern => "bit-pattern",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ValueStrategy> {
+        ALL_VALUE_STRATEGIES.iter().cloned().find(|strategy| strategy.name() == name)
+    }
+
+    pub fn choose(rng: &mut StdRng) -> ValueStrategy {
+        ALL_VALUE_STRATEGIES[rng.gen::<usize>() % ALL_VALUE_STRATEGIES.len()]
+    }
+
+    // Returns a value in the low `bits` bits; callers sign-extend it themselves if the field is signed.
+    //  Uniform draws a single u32, so it produces the same values as drawing the field type directly. A field with no
+    //  bits is always 0, and fields wider than 32 bits get 32.
+    pub fn gen_bits(&self, rng: &mut StdRng, bits: u32) -> u32 {
+        if bits == 0 {
+            return 0;
+        }
+        let bits = cmp::min(bits, 32);
+
+        let mask = if bits >= 32 { 0xffffffff } else { (1 << bits) - 1 };
+        let value = match self {
+            &ValueStrategy::Uniform => rng.gen::<u32>(),
+            &ValueStrategy::BoundaryBiased => {
+                // Uniform values now and then keep the rest of the space reachable
+                if rng.gen_weighted_bool(4) {
+                    rng.gen::<u32>()
+                } else {
+                    let boundaries = boundaries(bits);
+                    boundaries[rng.gen::<usize>() % boundaries.len()]
+                }
+            }
+            &ValueStrategy::SmallMagnitude => {
+                let magnitude = rng.gen::<u32>() % 17;
+                if rng.gen() { magnitude } else { magnitude.wrapping_neg() }
+            }
+            &ValueStrategy::BitPattern => {
+                let bit = rng.gen::<u32>() % bits;
+                match rng.gen::<u32>() % 6 {
+                    0 => 1 << bit, // Walking one
+                    1 => !(1 << bit), // Walking zero
+                    2 => (1 << bit) - 1, // Low mask
+                    3 => 0xffffffff << bit, // High mask
+                    4 => if rng.gen() { 0x55555555 } else { 0xaaaaaaaa },
+                    _ => (rng.gen::<u8>() as u32) * 0x01010101, // Repeated byte
+                }
+            }
+        };
+        value & mask
+    }
+}
+
+fn boundaries(bits: u32) -> Vec<u32> {
+    let sign_bit = 1u32 << (bits - 1);
+    let mut ret = vec![
+        0,
+        1,
+        2,
+        0xffffffff, // -1
+        0xfffffffe, // -2
+        sign_bit.wrapping_sub(1), // Max signed
+        sign_bit.wrapping_sub(2),
+        sign_bit, // Min signed
+        sign_bit + 1,
+    ];
+
+    // Values that sit at the sign boundary of narrower fields, both as-is and sign-extended, since that's where
+    //  movea/addi/xb/xh-style extension goes wrong
+    for &narrow_bits in [8, 16].iter() {
+        if narrow_bits < bits {
+            let narrow_sign_bit = 1u32 << (narrow_bits - 1);
+            ret.push(narrow_sign_bit - 1);
+            ret.push(narrow_sign_bit);
+            ret.push(!(narrow_sign_bit - 1));
+            ret.push(!narrow_sign_bit);
+            ret.push((1 << narrow_bits) - 1);
+            ret.push(1 << narrow_bits);
+        }
+    }
+
+    // Shift counts only use the low 5 bits, so values around 32 wrap
+    if bits > 5 {
+        ret.push(31);
+        ret.push(32);
+        ret.push(33);
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generators::build_rng;
+
+    #[test]
+    fn every_width_stays_in_its_field() {
+        let mut rng = build_rng(0);
+        for strategy in ALL_VALUE_STRATEGIES.iter() {
+            for bits in 0..34 {
+                for _ in 0..1000 {
+                    let value = strategy.gen_bits(&mut rng, bits);
+                    if bits < 32 {
+                        assert_eq!(value >> bits, 0, "{} gave {:#x} for {} bits", strategy.name(), value, bits);
+                    }
+                }
+            }
+        }
+    }
+}
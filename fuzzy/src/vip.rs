@@ -0,0 +1,187 @@
+use rand::{Rng, StdRng};
+
+use asm::*;
+use generators::Generator;
+
+// VIP control regs, as offsets from 0x0005f800
+pub const INTPND: i16 = 0x00;
+pub const INTCLR: i16 = 0x04;
+const DPSTTS: i16 = 0x20;
+const DPCTRL: i16 = 0x22;
+const BRTA: i16 = 0x24;
+const BRTB: i16 = 0x26;
+const BRTC: i16 = 0x28;
+const REST: i16 = 0x2a;
+const FRMCYC: i16 = 0x2e;
+const CTA: i16 = 0x30;
+const XPSTTS: i16 = 0x40;
+pub const XPCTRL: i16 = 0x42;
+const VER: i16 = 0x44;
+const SPT: [i16; 4] = [0x48, 0x4a, 0x4c, 0x4e];
+// GPLT0-3, then JPLT0-3
+const PALETTES: [i16; 8] = [0x60, 0x62, 0x64, 0x66, 0x68, 0x6a, 0x6c, 0x6e];
+
+pub const INTPND_FRAMESTART: u16 = 1 << 4;
+pub const INTPND_XPEND: u16 = 1 << 14;
+
+pub const XPCTRL_XPEN: u16 = 0x0002;
+
+// DISP, RE, SYNCE, LOCK. DPRST is left out; it isn't needed to get the display going.
+const DPCTRL_SAFE_BITS: u16 = 0x0002 | 0x0100 | 0x0200 | 0x0400;
+// XPEN and SBCMP. XPRST is left out for the same reason.
+const XPCTRL_SAFE_BITS: u16 = 0x0002 | 0x1f00;
+
+// What the loader leaves the display set up with (see loader.c), so its console keeps working after a test
+const LOADER_DISPLAY: &'static [(i16, u16)] = &[
+    (DPCTRL, 0x0002 | 0x0100 | 0x0200),
+    (XPCTRL, XPCTRL_XPEN),
+    (BRTA, 32),
+    (BRTB, 64),
+    (BRTC, 32),
+    (REST, 0),
+    (FRMCYC, 0),
+];
+const LOADER_PALETTE: u16 = 0xe4;
+
+// Regs VIP programs keep for themselves. Everything from FIRST_READBACK_REG up holds one of VipRegs' readbacks.
+pub const BASE_REG: u16 = 10;
+pub const VALUE_REG: u16 = 11;
+pub const POLL_REG: u16 = 12;
+pub const COUNTER_REG: u16 = 13;
+const FIRST_READBACK_REG: u16 = 14;
+
+const NUM_OPS: usize = 32;
+const MAX_DELAY_ITERATIONS: u16 = 0x2000;
+
+// Writes random (but safe) values to the VIP's control regs, waits a while between them, and reads back the
+//  readable regs and status bits into r14-r31, so they get compared like any other result.
+//
+// Everything the VIP does runs relative to its frame timing, so the program first waits for a frame to start, and
+//  the regs it writes are cleared first so leftovers from other tests (or boot) can't show up in the readbacks.
+//  Afterwards they're put back the way the loader had them.
+pub struct VipRegs {
+    rng: StdRng,
+}
+
+impl VipRegs {
+    pub fn new(rng: StdRng) -> VipRegs {
+        VipRegs {
+            rng: rng,
+        }
+    }
+
+    fn random_write(&mut self) -> (i16, u16) {
+        let value = self.rng.gen::<u16>();
+        match self.rng.gen::<u32>() % 7 {
+            0 => (DPCTRL, value & DPCTRL_SAFE_BITS),
+            // Kept low, so the displays are never driven hard
+            1 => (*self.rng.choose(&[BRTA, BRTB, BRTC]).unwrap(), value & 0x7f),
+            2 => (REST, value & 0xff),
+            3 => (FRMCYC, value & 0x0f),
+            4 => (XPCTRL, value & XPCTRL_SAFE_BITS),
+            5 => (*self.rng.choose(&SPT).unwrap(), value & 0x03ff),
+            _ => (*self.rng.choose(&PALETTES).unwrap(), value & 0xfc),
+        }
+    }
+
+    fn random_readable(&mut self) -> i16 {
+        match self.rng.gen::<u32>() % 6 {
+            0 => INTPND,
+            1 => DPSTTS,
+            2 => CTA,
+            3 => XPSTTS,
+            4 => *self.rng.choose(&SPT).unwrap(),
+            _ => *self.rng.choose(&PALETTES).unwrap(),
+        }
+    }
+}
+
+impl Generator for VipRegs {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        load_base(buf);
+
+        clear_writable_regs(buf);
+
+        wait_for_interrupt(buf, INTPND_FRAMESTART);
+
+        let mut readback_reg = FIRST_READBACK_REG;
+        for _ in 0..NUM_OPS {
+            match self.rng.gen::<u32>() % 10 {
+                0 | 1 | 2 | 3 | 4 => {
+                    let (offset, value) = self.random_write();
+                    movea(buf, value as i16, 0, VALUE_REG);
+                    st_h(buf, VALUE_REG, offset, BASE_REG);
+                }
+                5 | 6 => {
+                    let iterations = self.rng.gen::<u16>() % MAX_DELAY_ITERATIONS + 1;
+                    movea(buf, iterations as i16, 0, COUNTER_REG);
+                    add_imm(buf, -1, COUNTER_REG);
+                    bcond(buf, COND_NZ, -2);
+                }
+                _ if readback_reg < 32 => {
+                    let offset = self.random_readable();
+                    ld_h(buf, offset, BASE_REG, readback_reg);
+                    readback_reg += 1;
+                }
+                _ => (),
+            }
+        }
+
+        // Whatever's left gets the final state, with the version (which should never differ) last
+        while readback_reg < 32 {
+            let offset = if readback_reg == 31 { VER } else { self.random_readable() };
+            ld_h(buf, offset, BASE_REG, readback_reg);
+            readback_reg += 1;
+        }
+
+        restore_loader_display(buf);
+    }
+}
+
+// Points BASE_REG at the VIP's regs
+pub fn load_base(buf: &mut Vec<u8>) {
+    // 0x00060000 - 0x800 = 0x0005f800
+    movhi(buf, 0x0006, 0, BASE_REG);
+    movea(buf, -0x800, BASE_REG, BASE_REG);
+}
+
+// Clears INTPND, waits for any of the given bits to come up (or gives up), then forgets how long that took
+pub fn wait_for_interrupt(buf: &mut Vec<u8>, bits: u16) {
+    movea(buf, -1, 0, VALUE_REG);
+    st_h(buf, VALUE_REG, INTCLR, BASE_REG);
+    // 0x10000 tries, which takes well over a frame (20ms)
+    movhi(buf, 1, 0, COUNTER_REG);
+    ld_h(buf, INTPND, BASE_REG, POLL_REG);
+    andi(buf, bits, POLL_REG, POLL_REG);
+    bcond(buf, COND_NZ, 6);
+    add_imm(buf, -1, COUNTER_REG);
+    bcond(buf, COND_NZ, -12);
+    mov(buf, 0, COUNTER_REG);
+    mov(buf, 0, POLL_REG);
+    cmp(buf, 0, 0);
+}
+
+fn clear_writable_regs(buf: &mut Vec<u8>) {
+    let offsets = [DPCTRL, BRTA, BRTB, BRTC, REST, FRMCYC, XPCTRL].iter()
+        .chain(SPT.iter())
+        .chain(PALETTES.iter())
+        .cloned()
+        .collect::<Vec<_>>();
+    for offset in offsets {
+        st_h(buf, 0, offset, BASE_REG);
+    }
+}
+
+fn restore_loader_display(buf: &mut Vec<u8>) {
+    for &(offset, value) in LOADER_DISPLAY.iter() {
+        movea(buf, value as i16, 0, VALUE_REG);
+        st_h(buf, VALUE_REG, offset, BASE_REG);
+    }
+    movea(buf, LOADER_PALETTE as i16, 0, VALUE_REG);
+    for &offset in PALETTES.iter() {
+        st_h(buf, VALUE_REG, offset, BASE_REG);
+    }
+    for &offset in SPT.iter() {
+        st_h(buf, 0, offset, BASE_REG);
+    }
+}
@@ -0,0 +1,253 @@
+use rand::{Rng, StdRng};
+
+use asm::*;
+use command;
+use generators::{Generator, Ret};
+use placement;
+use target::Target;
+use vip::{self, BASE_REG, COUNTER_REG, POLL_REG, VALUE_REG};
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+const DISPLAY_WIDTH: usize = 384;
+const DISPLAY_HEIGHT: usize = 224;
+
+// Framebuffers are stored a column at a time, 4 pixels per byte, with room for 256 rows
+const FRAMEBUFFER_COLUMN_LEN: usize = 64;
+const FRAMEBUFFER_LEN: u32 = (DISPLAY_WIDTH * FRAMEBUFFER_COLUMN_LEN) as u32;
+pub const FRAMEBUFFERS: &'static [(&'static str, u32)] = &[
+    ("left0", 0x00000000),
+    ("left1", 0x00008000),
+    ("right0", 0x00010000),
+    ("right1", 0x00018000),
+];
+
+// The loader's font lives in CharSeg0, BGMap 0 and world 31, so scenes use CharSeg1 and BGMap 1, and only borrow
+//  world 31 while they draw
+const SCENE_CHARS_ADDR: u32 = 0x0000e000;
+const NUM_SCENE_CHARS: usize = 16;
+const CHAR_LEN: usize = 16;
+// First char of CharSeg1
+const SCENE_CHAR_BASE: u16 = 512;
+const SCENE_BGMAP: u16 = 1;
+const BGMAP_CELLS: u16 = 64 * 64;
+
+const WORLD_LON: u16 = 0x8000;
+const WORLD_RON: u16 = 0x4000;
+const WORLD_END: u16 = 0x0040;
+const WORLD_HALFWORDS: u16 = 16;
+
+// Char, flips and palette
+const CELL_RANDOM_BITS: u16 = 0xf000 | (NUM_SCENE_CHARS as u16 - 1);
+
+// Holds world 31's addr, with the loader's world 31 saved in the regs from SAVED_WORLD_REG up
+const WORLD_REG: u16 = 14;
+const POINTER_REG: u16 = 15;
+const SAVED_WORLD_REG: u16 = 16;
+
+// Draws a random scene: random chars, a BGMap full of them (with random flips and palettes), and a world showing
+//  part of it at a random position and size. It draws two frames, so both framebuffer pairs hold the scene, then
+//  stops drawing so they stay put until they're read back.
+pub struct VramScene {
+    rng: StdRng,
+}
+
+impl VramScene {
+    pub fn new(rng: StdRng) -> VramScene {
+        VramScene {
+            rng: rng,
+        }
+    }
+
+    fn random_world(&mut self) -> [u16; 16] {
+        let mut ret = [0; 16];
+        ret[0] = WORLD_LON | WORLD_RON | SCENE_BGMAP;
+        // gx, gp, gy
+        ret[1] = self.rng.gen_range(-64i16, DISPLAY_WIDTH as i16) as u16;
+        ret[2] = self.rng.gen_range(-8i16, 9) as u16;
+        ret[3] = self.rng.gen_range(-32i16, DISPLAY_HEIGHT as i16) as u16;
+        // mx, mp, my
+        ret[4] = self.rng.gen_range(0, 512);
+        ret[5] = self.rng.gen_range(-8i16, 9) as u16;
+        ret[6] = self.rng.gen_range(0, 512);
+        // w, h (both one less than the actual size)
+        ret[7] = self.rng.gen_range(7, DISPLAY_WIDTH as u16);
+        ret[8] = self.rng.gen_range(7, DISPLAY_HEIGHT as u16);
+        ret
+    }
+}
+
+impl Generator for VramScene {
+    fn next(&mut self, buf: &mut Vec<u8>) {
+        vip::load_base(buf);
+
+        // The loader's console would otherwise be drawn over the scene, or halfway through setting it up
+        st_h(buf, 0, vip::XPCTRL, BASE_REG);
+
+        // 0x00040000 - 0x2420 = 0x0003dbe0 (world 31)
+        movhi(buf, 0x0004, 0, WORLD_REG);
+        movea(buf, -0x2420, WORLD_REG, WORLD_REG);
+        for index in 0..WORLD_HALFWORDS {
+            ld_h(buf, (index * 2) as i16, WORLD_REG, SAVED_WORLD_REG + index);
+        }
+
+        // 0x00010000 - 0x2000 = 0x0000e000
+        movhi(buf, 0x0001, 0, POINTER_REG);
+        movea(buf, -0x2000, POINTER_REG, POINTER_REG);
+        for index in 0..NUM_SCENE_CHARS * CHAR_LEN / 2 {
+            movea(buf, self.rng.gen::<i16>(), 0, VALUE_REG);
+            st_h(buf, VALUE_REG, (index * 2) as i16, POINTER_REG);
+        }
+
+        // Fill the whole BGMap with cells from a counter stepping by a random amount
+        movhi(buf, 0x0002, 0, POINTER_REG);
+        movea(buf, (SCENE_BGMAP * 0x2000) as i16, POINTER_REG, POINTER_REG);
+        movea(buf, BGMAP_CELLS as i16, 0, COUNTER_REG);
+        movea(buf, self.rng.gen::<i16>(), 0, POLL_REG);
+        let step = self.rng.gen::<i16>() | 1;
+        andi(buf, CELL_RANDOM_BITS, POLL_REG, VALUE_REG);
+        ori(buf, SCENE_CHAR_BASE, VALUE_REG, VALUE_REG);
+        st_h(buf, VALUE_REG, 0, POINTER_REG);
+        add_imm(buf, 2, POINTER_REG);
+        addi(buf, step, POLL_REG, POLL_REG);
+        add_imm(buf, -1, COUNTER_REG);
+        bcond(buf, COND_NZ, -20);
+
+        let world = self.random_world();
+        for (index, &value) in world.iter().enumerate() {
+            movea(buf, value as i16, 0, VALUE_REG);
+            st_h(buf, VALUE_REG, (index * 2) as i16, WORLD_REG);
+        }
+        // World 30 ends the list
+        movea(buf, WORLD_END as i16, 0, VALUE_REG);
+        st_h(buf, VALUE_REG, -(WORLD_HALFWORDS as i16 * 2), WORLD_REG);
+
+        movea(buf, vip::XPCTRL_XPEN as i16, 0, VALUE_REG);
+        st_h(buf, VALUE_REG, vip::XPCTRL, BASE_REG);
+        vip::wait_for_interrupt(buf, vip::INTPND_XPEND);
+        vip::wait_for_interrupt(buf, vip::INTPND_XPEND);
+        st_h(buf, 0, vip::XPCTRL, BASE_REG);
+
+        // Give the loader its world back, and drop the copy, which may well differ between targets
+        for index in 0..WORLD_HALFWORDS {
+            st_h(buf, SAVED_WORLD_REG + index, (index * 2) as i16, WORLD_REG);
+            mov(buf, 0, SAVED_WORLD_REG + index);
+        }
+    }
+}
+
+pub struct Vram {
+    // In FRAMEBUFFERS order
+    pub framebuffers: Vec<Vec<u8>>,
+    pub chars: Vec<u8>,
+}
+
+pub fn capture(target: &mut Box<Target>) -> Result<Vram, command::Error> {
+    let mut framebuffers = Vec::new();
+    for &(_, addr) in FRAMEBUFFERS.iter() {
+        framebuffers.push(target.read_mem(addr, FRAMEBUFFER_LEN)?);
+    }
+
+    Ok(Vram {
+        framebuffers: framebuffers,
+        chars: target.read_mem(SCENE_CHARS_ADDR, (NUM_SCENE_CHARS * CHAR_LEN) as u32)?,
+    })
+}
+
+// Scenes leave drawing off so the framebuffers survive until they're captured; this turns it back on for the loader
+pub fn resume_drawing(target: &mut Box<Target>) -> Result<(), command::Error> {
+    let mut rom = Vec::new();
+    vip::load_base(&mut rom);
+    movea(&mut rom, vip::XPCTRL_XPEN as i16, 0, VALUE_REG);
+    st_h(&mut rom, VALUE_REG, vip::XPCTRL, BASE_REG);
+    Ret.next(&mut rom);
+
+    target.reset()?;
+    target.run_test(placement::DEFAULT_ROM_ADDR, &rom, &[0; 32]).map(|_| ())
+}
+
+// <target>-<framebuffer>.ppm for each framebuffer, and <target>-chars.ppm with the scene's chars in a row
+pub fn write_images<P: AsRef<Path>>(dir: P, target_name: &str, vram: &Vram) -> io::Result<()> {
+    for (&(name, _), framebuffer) in FRAMEBUFFERS.iter().zip(vram.framebuffers.iter()) {
+        let pixels = framebuffer_pixels(framebuffer);
+        write_ppm(dir.as_ref().join(format!("{}-{}.ppm", target_name, name)), DISPLAY_WIDTH, DISPLAY_HEIGHT, &shades_to_rgb(&pixels))?;
+    }
+
+    let (width, height, pixels) = char_pixels(&vram.chars);
+    write_ppm(dir.as_ref().join(format!("{}-chars.ppm", target_name)), width, height, &shades_to_rgb(&pixels))
+}
+
+// diff-<target>-<framebuffer>.ppm (and diff-<target>-chars.ppm) against the reference target's capture, with
+//  differing pixels in white over a dimmed copy of the reference. Returns how many pixels differ in each.
+pub fn write_diffs<P: AsRef<Path>>(dir: P, target_name: &str, reference: &Vram, vram: &Vram) -> io::Result<Vec<(&'static str, usize)>> {
+    let mut ret = Vec::new();
+
+    for (index, &(name, _)) in FRAMEBUFFERS.iter().enumerate() {
+        let reference_pixels = framebuffer_pixels(&reference.framebuffers[index]);
+        let pixels = framebuffer_pixels(&vram.framebuffers[index]);
+        let (rgb, num_differing) = diff_to_rgb(&reference_pixels, &pixels);
+        write_ppm(dir.as_ref().join(format!("diff-{}-{}.ppm", target_name, name)), DISPLAY_WIDTH, DISPLAY_HEIGHT, &rgb)?;
+        ret.push((name, num_differing));
+    }
+
+    let (width, height, reference_pixels) = char_pixels(&reference.chars);
+    let (_, _, pixels) = char_pixels(&vram.chars);
+    let (rgb, num_differing) = diff_to_rgb(&reference_pixels, &pixels);
+    write_ppm(dir.as_ref().join(format!("diff-{}-chars.ppm", target_name)), width, height, &rgb)?;
+    ret.push(("chars", num_differing));
+
+    Ok(ret)
+}
+
+// One shade (0-3) per pixel, row by row
+fn framebuffer_pixels(framebuffer: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(DISPLAY_WIDTH * DISPLAY_HEIGHT);
+    for y in 0..DISPLAY_HEIGHT {
+        for x in 0..DISPLAY_WIDTH {
+            let byte = framebuffer[x * FRAMEBUFFER_COLUMN_LEN + y / 4];
+            ret.push((byte >> ((y % 4) * 2)) & 0x03);
+        }
+    }
+    ret
+}
+
+// Chars are 8 rows of 8 pixels, each row a halfword with the leftmost pixel in the low bits
+fn char_pixels(chars: &[u8]) -> (usize, usize, Vec<u8>) {
+    let num_chars = chars.len() / CHAR_LEN;
+    let width = num_chars * 8;
+    let height = 8;
+    let mut ret = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let byte = chars[(x / 8) * CHAR_LEN + y * 2 + (x % 8) / 4];
+            ret.push((byte >> ((x % 4) * 2)) & 0x03);
+        }
+    }
+    (width, height, ret)
+}
+
+fn shades_to_rgb(pixels: &[u8]) -> Vec<u8> {
+    pixels.iter().flat_map(|&shade| vec![shade * 85, 0, 0]).collect()
+}
+
+fn diff_to_rgb(reference: &[u8], pixels: &[u8]) -> (Vec<u8>, usize) {
+    let mut num_differing = 0;
+    let mut rgb = Vec::with_capacity(reference.len() * 3);
+    for (&a, &b) in reference.iter().zip(pixels.iter()) {
+        if a == b {
+            rgb.extend(&[a * 20, a * 20, a * 20]);
+        } else {
+            num_differing += 1;
+            rgb.extend(&[255, 255, 255]);
+        }
+    }
+    (rgb, num_differing)
+}
+
+fn write_ppm<P: AsRef<Path>>(path: P, width: usize, height: usize, rgb: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    write!(file, "P6\n{} {}\n255\n", width, height)?;
+    file.write_all(rgb)
+}
@@ -1,5 +1,36 @@
     .lcomm preserveStackPointer, 4
 
+    /* Hardware timer, used as a watchdog while tests execute */
+    timerRegs = 0x02000000
+    timerTlr = 0x18
+    timerThr = 0x1c
+    timerTcr = 0x20
+
+    timerEnable = 0x01
+    timerZeroStatusClear = 0x04
+    timerInterruptEnable = 0x08
+    timerClockSelect20us = 0x10
+
+    /* Reload value in 20us ticks (500ms); tests take a few ms at most, so this is very generous */
+    /*  Must fit in movea's signed 16-bit immediate. */
+    watchdogReload = 25000
+
+    /* Tests that use the timer themselves get their interrupts counted here instead of aborting them (see */
+    /*  _executeHarnessTimerInterrupt). It sits right after the result regs, at a fixed addr tests can find. */
+    timerRecord = 0x0001e180
+    timerRecordBudget = 0
+    timerRecordCount = 4
+    timerRecordLastEipc = 8
+    timerRecordLastEipsw = 12
+    timerRecordSavedR2 = 16
+
+    /* PSW interrupt disable (ID) bit */
+    pswInterruptDisable = 0x1000
+
+    /* Values written to the result status word */
+    resultStatusCompleted = 0
+    resultStatusTimedOut = 1
+
     .section .text
     .align 1
 
@@ -46,10 +77,40 @@ _executeHarness:
     movea lo(preserveStackPointer), r1, r1
     st.w sp, 0[r1]
 
-    /* Load jump addr arg into r30. Unfortunately this means no initial value for r30, but that's ok. */
-    mov r6, r30
+    /* Enter the test with reti, which jumps to eipc with psw = eipsw, so no reg is needed to hold the jump addr. */
+    /*  Starting with a clear psw also enables interrupts, which the watchdog relies on. Until then they're disabled, */
+    /*  since taking one would overwrite eipc. */
+    movea pswInterruptDisable, r0, r1
+    ldsr r1, psw
+    ldsr r6, eipc
+    ldsr r0, eipsw
+
+    /* Every test starts with an empty, disabled instruction cache, whatever the last one left behind. */
+    /*  ICC with CEC = 128 clears every entry. */
+    ori 0x8001, r0, r1
+    ldsr r1, chcw
+    ldsr r0, chcw
+
+    /* No budget, so timer interrupts mean the watchdog went off until the test says otherwise */
+    movhi hi(timerRecord), r0, r1
+    movea lo(timerRecord), r1, r1
+    st.w r0, timerRecordBudget[r1]
+    st.w r0, timerRecordCount[r1]
+    st.w r0, timerRecordLastEipc[r1]
+    st.w r0, timerRecordLastEipsw[r1]
 
-    /* Load initial reg values, minus r30 and r31 */
+    /* Arm watchdog. If the test doesn't return before the timer hits zero, the timer interrupt aborts it. */
+    /*  The timer only counts while enabled, so reload values must be written before enabling it. */
+    movhi hi(timerRegs), r0, r1
+    st.b r0, timerTcr[r1]
+    movea watchdogReload, r0, r2
+    st.b r2, timerTlr[r1]
+    shr 8, r2
+    st.b r2, timerThr[r1]
+    movea timerEnable | timerZeroStatusClear | timerInterruptEnable | timerClockSelect20us, r0, r2
+    st.b r2, timerTcr[r1]
+
+    /* Load initial reg values, r31 (the base) last */
     initialRegValues = 0x0001e000
     movhi hi(initialRegValues), r0, r31
     movea lo(initialRegValues), r31, r31
@@ -84,22 +145,23 @@ _executeHarness:
     ld.w 108[r31], r27
     ld.w 112[r31], r28
     ld.w 116[r31], r29
-
-    /* Clear PSW */
-    ldsr r0, psw
+    ld.w 120[r31], r30
+    ld.w 124[r31], r31
 
     /* Actual test stuff :) */
+    reti
 
-    /* Set link reg by hand because we only have relative JAL */
-    movhi hi(executeRet), r0, r31
-    movea lo(executeRet), r31, r31
+    .global _executeHarnessTrap
 
-    jmp [r30]
+_executeHarnessTrap:
+    /* Tests end with trap 0 instead of returning through a link reg, so every reg is theirs. The trap vector jumps */
+    /*  here with all of the test's regs intact, its pc in eipc and its psw in eipsw, and interrupts disabled. */
+    /*  fepc is free outside of duplexed exceptions, so it holds r31 while r31 is used as the base. */
+    ldsr r31, fepc
 
-executeRet:
-    /* Output result reg values (minus r31 but including psw) */
-    stsr psw, r31
-    ldsr r31, fepsw
+    /* Disarm watchdog. This also freezes the timer counter, which is used to report elapsed time below. */
+    movhi hi(timerRegs), r0, r31
+    st.b r0, timerTcr[r31]
 
     resultRegValues = initialRegValues + 32 * 4
     movhi hi(resultRegValues), r0, r31
@@ -137,16 +199,40 @@ executeRet:
     st.w r29, 116[r31]
     st.w r30, 120[r31]
 
-    stsr fepsw, r1
+    stsr fepc, r1
     st.w r1, 124[r31]
 
+    stsr eipsw, r1
+    st.w r1, 128[r31]
+
+    resultStatus = resultRegValues + 33 * 4
+    movea resultStatusCompleted, r0, r1
+    st.w r1, 132[r31]
+
+    /* Elapsed time in 20us ticks, including a small constant harness overhead */
+    resultElapsedTicks = resultStatus + 4
+    movhi hi(timerRegs), r0, r1
+    ld.b timerThr[r1], r2
+    andi 0xff, r2, r2
+    shl 8, r2
+    ld.b timerTlr[r1], r3
+    andi 0xff, r3, r3
+    or r3, r2
+    movea watchdogReload, r0, r1
+    sub r2, r1
+    st.w r1, 136[r31]
+
+executeRestore:
     /* Restore reg values */
     movhi hi(preserveStackPointer), r0, r1
     movea lo(preserveStackPointer), r1, r1
     ld.w 0[r1], sp
 
+    /* Restoring PSW also leaves exception/interrupt context, whether we got here via the trap or the watchdog */
     ld.w 120[sp], r1
     ldsr r1, psw
+    /* The loader runs with the cache disabled, even if the test enabled it */
+    ldsr r0, chcw
     ld.w 0[sp], r1
     ld.w 4[sp], r2
     ld.w 8[sp], r4
@@ -181,3 +267,60 @@ executeRet:
 
     /* Return! */
     jmp [r31]
+
+    .global _executeHarnessTimerInterrupt
+
+_executeHarnessTimerInterrupt:
+    /* Entered from the timer interrupt vector with all of the test's regs intact. A test that programs the timer */
+    /*  itself sets a budget of interrupts in the timer record; each one is counted (along with where it hit) and */
+    /*  acknowledged, and the test carries on. With no budget left, this is the watchdog going off. */
+    /*  fepc holds r1 and the record holds r2 meanwhile. reti restores the psw, so the flags are free to trash. */
+    ldsr r1, fepc
+    movhi hi(timerRecord), r0, r1
+    movea lo(timerRecord), r1, r1
+    st.w r2, timerRecordSavedR2[r1]
+    ld.w timerRecordBudget[r1], r2
+    cmp r0, r2
+    bne timerInterruptCount
+    jr _executeHarnessTimeout
+
+timerInterruptCount:
+    add -1, r2
+    st.w r2, timerRecordBudget[r1]
+    ld.w timerRecordCount[r1], r2
+    add 1, r2
+    st.w r2, timerRecordCount[r1]
+    stsr eipc, r2
+    st.w r2, timerRecordLastEipc[r1]
+    stsr eipsw, r2
+    st.w r2, timerRecordLastEipsw[r1]
+
+    /* Acknowledge, leaving the rest of the test's timer settings alone */
+    movhi hi(timerRegs), r0, r2
+    ld.b timerTcr[r2], r1
+    ori timerZeroStatusClear, r1, r1
+    st.b r1, timerTcr[r2]
+
+    movhi hi(timerRecord), r0, r1
+    movea lo(timerRecord), r1, r1
+    ld.w timerRecordSavedR2[r1], r2
+    stsr fepc, r1
+    reti
+
+    .global _executeHarnessTimeout
+
+_executeHarnessTimeout:
+    /* Entered from _executeHarnessTimerInterrupt when the watchdog fires. */
+    /*  The test's reg values are lost at this point (r1 and r2 are clobbered), so only the status is reported. */
+
+    /* Disarm watchdog and acknowledge its interrupt */
+    movhi hi(timerRegs), r0, r1
+    movea timerZeroStatusClear, r0, r2
+    st.b r2, timerTcr[r1]
+
+    movhi hi(resultStatus), r0, r1
+    movea lo(resultStatus), r1, r1
+    movea resultStatusTimedOut, r0, r2
+    st.w r2, 0[r1]
+
+    jr executeRestore
@@ -232,10 +232,21 @@ static u8 responseBuffer[256];
 #define FUZZY_COMMAND_READ_MEM_REGION 0x02
 #define FUZZY_COMMAND_READ_MEM_REGION_DATA 0x03
 #define FUZZY_COMMAND_EXECUTE 0x04
+#define FUZZY_COMMAND_RUN_TEST 0x05
+#define FUZZY_COMMAND_RUN_TEST_DATA 0x06
+#define FUZZY_COMMAND_RUN_TEST_RESULT 0x07
+#define FUZZY_COMMAND_EXECUTE_BATCH 0x08
 
 #define FUZZY_RESPONSE_UNEXPECTED_COMMAND 0x00
 #define FUZZY_RESPONSE_OK_WITH_CRAPSUM 0x01
 #define FUZZY_RESPONSE_READ_MEM_REGION_DATA 0x02
+#define FUZZY_RESPONSE_RUN_TEST_RESULT 0x03
+
+// Must match execute_harness.s
+#define HARNESS_INITIAL_REG_VALUES 0x0001e000
+#define HARNESS_NUM_INITIAL_REGS 32
+#define HARNESS_RESULT_VALUES (HARNESS_INITIAL_REG_VALUES + 32 * 4)
+#define HARNESS_NUM_RESULT_WORDS 35 // r0-r31, psw, status, elapsed ticks
 
 extern void executeHarness(u32);
 
@@ -255,9 +266,35 @@ int fuzzyRespondOkWithCrapsum()
     return linkSendPacket(responseBuffer, 5);
 }
 
+int fuzzyRespondRunTestResult()
+{
+    const u8 *readPtr = (const u8 *)HARNESS_RESULT_VALUES;
+    int i;
+
+    responseBuffer[0] = FUZZY_RESPONSE_RUN_TEST_RESULT;
+    for (i = 0; i < HARNESS_NUM_RESULT_WORDS * 4; i++)
+        responseBuffer[i + 1] = readPtr[i];
+    return linkSendPacket(responseBuffer, 1 + HARNESS_NUM_RESULT_WORDS * 4);
+}
+
 #define COMMAND_OK 0
 #define COMMAND_ERR -1
 
+u32 fuzzyReadU32(const u8 *buf)
+{
+    u32 ret;
+    int i;
+
+    ret = 0;
+    for (i = 0; i < 4; i++)
+    {
+        ret >>= 8;
+        ret |= buf[i] << 24;
+    }
+
+    return ret;
+}
+
 int commandDispatch()
 {
     switch (receivePacketBuffer[0])
@@ -422,6 +459,164 @@ int commandDispatch()
         // Exchange complete
         //printStr("s.");
 
+        return COMMAND_OK;
+
+    case FUZZY_COMMAND_RUN_TEST:
+        // Composite command: load rom + initial regs, execute, and return results, without any
+        //  check status/read data round trips in between. Rom data follows in RUN_TEST_DATA packets.
+        {
+            u32 entry;
+            u8 *writePtr;
+            int remainingLen;
+            u8 *readPtr;
+            int i;
+
+            if (receivePacketLen != 1 + 4 + 4 + HARNESS_NUM_INITIAL_REGS * 4)
+            {
+                fuzzyRespondUnexpectedCommand();
+                return COMMAND_ERR;
+            }
+
+            // Send response packet
+            if (fuzzyRespondOkWithCrapsum() == LINK_ERR)
+                return COMMAND_ERR;
+
+            // Exchange complete
+            //printStr("s.");
+
+            entry = fuzzyReadU32(receivePacketBuffer + 1);
+            remainingLen = (int)fuzzyReadU32(receivePacketBuffer + 5);
+
+            writePtr = (u8 *)HARNESS_INITIAL_REG_VALUES;
+            readPtr = receivePacketBuffer + 9;
+            for (i = 0; i < HARNESS_NUM_INITIAL_REGS * 4; i++)
+                *(writePtr++) = *(readPtr++);
+
+            // Receive rom data
+            writePtr = (u8 *)entry;
+            while (remainingLen > 0)
+            {
+                int dataLen;
+
+                while (linkReceivePacket() == LINK_ERR)
+                    ;
+
+                //printStr("r");
+
+                dataLen = receivePacketLen - 1;
+                if (receivePacketBuffer[0] != FUZZY_COMMAND_RUN_TEST_DATA || dataLen > remainingLen)
+                {
+                    fuzzyRespondUnexpectedCommand();
+                    return COMMAND_ERR;
+                }
+
+                if (fuzzyRespondOkWithCrapsum() == LINK_ERR)
+                    return COMMAND_ERR;
+
+                // Exchange complete
+                //printStr("s.");
+
+                readPtr = receivePacketBuffer + 1;
+                for (i = 0; i < dataLen; i++)
+                    *(writePtr++) = *(readPtr++);
+
+                remainingLen -= dataLen;
+            }
+
+            executeHarness(entry);
+
+            // Expect run test result command
+            while (linkReceivePacket() == LINK_ERR)
+                ;
+
+            //printStr("r");
+
+            if (receivePacketBuffer[0] == FUZZY_COMMAND_RUN_TEST_RESULT)
+            {
+                if (fuzzyRespondRunTestResult() == LINK_ERR)
+                    return COMMAND_ERR;
+            }
+            else if (fuzzyRespondUnexpectedCommand() == LINK_ERR)
+                return COMMAND_ERR;
+
+            // Exchange complete
+            //printStr("s.");
+
+            return COMMAND_OK;
+        }
+
+    case FUZZY_COMMAND_EXECUTE_BATCH:
+        if (receivePacketLen != 1 + 4)
+        {
+            fuzzyRespondUnexpectedCommand();
+            return COMMAND_ERR;
+        }
+
+        // Send response packet
+        if (fuzzyRespondOkWithCrapsum() == LINK_ERR)
+            return COMMAND_ERR;
+
+        // Exchange complete
+        //printStr("s.");
+
+        // Execute command
+        //  The batch table is a case count followed by one { entry, initial regs, result words } record per case.
+        //  Each case runs through the normal harness, and its results are copied back into its own record.
+        {
+            u32 *tablePtr;
+            u32 numCases;
+            u32 *initialRegs;
+            u32 *results;
+            u32 i;
+            int j;
+
+            tablePtr = (u32 *)fuzzyReadU32(receivePacketBuffer + 1);
+            numCases = *(tablePtr++);
+
+            initialRegs = (u32 *)HARNESS_INITIAL_REG_VALUES;
+            results = (u32 *)HARNESS_RESULT_VALUES;
+
+            for (i = 0; i < numCases; i++)
+            {
+                u32 entry = *(tablePtr++);
+
+                for (j = 0; j < HARNESS_NUM_INITIAL_REGS; j++)
+                    initialRegs[j] = *(tablePtr++);
+
+                executeHarness(entry);
+
+                for (j = 0; j < HARNESS_NUM_RESULT_WORDS; j++)
+                    *(tablePtr++) = results[j];
+            }
+        }
+
+        // Expect check status command
+        while (linkReceivePacket() == LINK_ERR)
+            ;
+
+        //printStr("r");
+
+        if (receivePacketBuffer[0] == FUZZY_COMMAND_CHECK_STATUS)
+        {
+            if (fuzzyRespondOkWithCrapsum() == LINK_ERR)
+                return COMMAND_ERR;
+        }
+        else if (fuzzyRespondUnexpectedCommand() == LINK_ERR)
+            return COMMAND_ERR;
+
+        // Exchange complete
+        //printStr("s.");
+
+        return COMMAND_OK;
+
+    case FUZZY_COMMAND_RUN_TEST_RESULT:
+        // Results stay put until the next test, so a host that lost the response packet can simply ask again
+        if (fuzzyRespondRunTestResult() == LINK_ERR)
+            return COMMAND_ERR;
+
+        // Exchange complete
+        //printStr("s.");
+
         return COMMAND_OK;
     }
 
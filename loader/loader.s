@@ -105,8 +105,9 @@ _interrupt_table_controller:
 
     /* INTTIM (7FFFE10h) - Timer Interrupt */
 _interrupt_table_timer:
-    reti
-    .fill   0x0e
+    /* The execute harness watchdog, or counted for tests that use the timer. jr doesn't touch any regs. */
+    jr      _executeHarnessTimerInterrupt
+    .fill   0x0C
 
     /* INTCRO (7FFFE20h) - Expansion Port Interrupt */
 _interrupt_table_expansion:
@@ -151,8 +152,9 @@ _interrupt_table_invalid_opcode:
 
     /* (7FFFFA0h) - Trap 0 exception */
 _interrupt_table_trap_0:
-    reti
-    .fill   0x0E
+    /* Tests end with trap 0 to get back to the execute harness. jr doesn't touch any regs. */
+    jr      _executeHarnessTrap
+    .fill   0x0C
 
     /* (7FFFFB0h) - Trap 1 exception */
 _interrupt_table_trap_1: